@@ -0,0 +1,152 @@
+//! Streams a scrolling gradient to the panel through PIO+DMA
+//!
+//! Each line is rendered into one RAM buffer while the previous one is being sent by DMA.
+#![no_std]
+#![no_main]
+
+use bsp::entry;
+use cortex_m::singleton;
+use defmt::*;
+use defmt_rtt as _;
+use fugit::RateExtU32;
+use hal::{
+    clocks::{ClocksManager, InitError},
+    dma::DMAExt,
+    gpio::{FunctionPio0, Pin},
+    pac,
+    pac::vreg_and_chip_reset::vreg::VSEL_A,
+    pio::PIOExt,
+    pll::{common_configs::PLL_USB_48MHZ, setup_pll_blocking},
+    sio::Sio,
+    vreg::set_voltage,
+    // watchdog::Watchdog,
+    xosc::setup_xosc_blocking,
+    Clock,
+};
+use panic_halt as _;
+use rp2040_hal as hal;
+
+const XOSC_CRYSTAL_FREQ: u32 = 12_000_000; // Typically found in BSP crates
+use rp_pico as bsp;
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+use lib::{overclock, PioDmaBus, ILI9488, ILI9488_T_WC_NS};
+use overclock::overclock_configs::PLL_SYS_240MHZ;
+
+#[entry]
+fn main() -> ! {
+    info!("Program start");
+    let mut pac = pac::Peripherals::take().unwrap();
+    let core = pac::CorePeripherals::take().unwrap();
+    // let mut watchdog = Watchdog::new(pac.WATCHDOG);
+    let sio = Sio::new(pac.SIO);
+
+    set_voltage(&mut pac.VREG_AND_CHIP_RESET, VSEL_A::VOLTAGE1_10);
+
+    let xosc = setup_xosc_blocking(pac.XOSC, XOSC_CRYSTAL_FREQ.Hz())
+        .map_err(InitError::XoscErr)
+        .ok()
+        .unwrap();
+    let mut clocks = ClocksManager::new(pac.CLOCKS);
+
+    let pll_sys = setup_pll_blocking(
+        pac.PLL_SYS,
+        xosc.operating_frequency(),
+        PLL_SYS_240MHZ,
+        &mut clocks,
+        &mut pac.RESETS,
+    )
+    .map_err(InitError::PllError)
+    .unwrap();
+    let pll_usb = setup_pll_blocking(
+        pac.PLL_USB,
+        xosc.operating_frequency(),
+        PLL_USB_48MHZ,
+        &mut clocks,
+        &mut pac.RESETS,
+    )
+    .map_err(InitError::PllError)
+    .unwrap();
+
+    clocks
+        .init_default(&xosc, &pll_sys, &pll_usb)
+        .map_err(InitError::ClockError)
+        .unwrap();
+
+    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
+    let pins = hal::gpio::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let wr: Pin<_, FunctionPio0, _> = pins.gpio19.into_function();
+    let wr_pin_id = wr.id().num;
+
+    let dc = pins.gpio20.into_push_pull_output();
+    let rst = pins.gpio22.into_push_pull_output();
+    let bl = pins.gpio28.into_push_pull_output();
+
+    let lcd_d0: Pin<_, FunctionPio0, _> = pins.gpio0.into_function();
+    let _lcd_d1: Pin<_, FunctionPio0, _> = pins.gpio1.into_function();
+    let _lcd_d2: Pin<_, FunctionPio0, _> = pins.gpio2.into_function();
+    let _lcd_d3: Pin<_, FunctionPio0, _> = pins.gpio3.into_function();
+    let _lcd_d4: Pin<_, FunctionPio0, _> = pins.gpio4.into_function();
+    let _lcd_d5: Pin<_, FunctionPio0, _> = pins.gpio5.into_function();
+    let _lcd_d6: Pin<_, FunctionPio0, _> = pins.gpio6.into_function();
+    let _lcd_d7: Pin<_, FunctionPio0, _> = pins.gpio7.into_function();
+    let _lcd_d8: Pin<_, FunctionPio0, _> = pins.gpio8.into_function();
+    let _lcd_d9: Pin<_, FunctionPio0, _> = pins.gpio9.into_function();
+    let _lcd_d10: Pin<_, FunctionPio0, _> = pins.gpio10.into_function();
+    let _lcd_d11: Pin<_, FunctionPio0, _> = pins.gpio11.into_function();
+    let _lcd_d12: Pin<_, FunctionPio0, _> = pins.gpio12.into_function();
+    let _lcd_d13: Pin<_, FunctionPio0, _> = pins.gpio13.into_function();
+    let _lcd_d14: Pin<_, FunctionPio0, _> = pins.gpio14.into_function();
+    let _lcd_d15: Pin<_, FunctionPio0, _> = pins.gpio15.into_function();
+
+    let lcd_d0_pin_id = lcd_d0.id().num;
+
+    let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
+    let dma = pac.DMA.split(&mut pac.RESETS);
+    let (di, _sm) = PioDmaBus::setup(
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+        ILI9488_T_WC_NS,
+        wr_pin_id,
+        lcd_d0_pin_id,
+        dma.ch0,
+        dc,
+    )
+    .unwrap();
+
+    info!("PIO block setuped");
+
+    let mut display = ILI9488::new(di, Some(rst), Some(bl), 480, 320);
+    display.init(&mut delay).unwrap();
+
+    // Two line buffers: one is streamed by DMA while the other is rendered.
+    let mut front = Some(singleton!(: [u16; 480] = [0; 480]).unwrap());
+    let mut back = singleton!(: [u16; 480] = [0; 480]).unwrap();
+
+    let mut frame: u32 = 0;
+    loop {
+        display.set_addr_win(0, 0, 479, 319).unwrap();
+        for y in 0..320u32 {
+            for (x, px) in back.iter_mut().enumerate() {
+                let r = (x as u32 + frame) as u8;
+                let g = (y + frame) as u8;
+                let b = (x as u32 + y) as u8;
+                *px = Rgb565::new(r >> 3, g >> 2, b >> 3).into_storage();
+            }
+            let done = display.di_mut().start_transfer(back).unwrap();
+            back = done.or(front.take()).unwrap();
+        }
+        front = display.di_mut().wait_transfer();
+        frame = frame.wrapping_add(4);
+    }
+}
+
+// End of file
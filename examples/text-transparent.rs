@@ -15,7 +15,7 @@ use hal::{
     gpio::{FunctionPio0, Pin},
     pac,
     pac::vreg_and_chip_reset::vreg::VSEL_A,
-    pio::{Buffers, PIOExt, ShiftDirection},
+    pio::PIOExt,
     pll::{common_configs::PLL_USB_48MHZ, setup_pll_blocking},
     sio::Sio,
     vreg::set_voltage,
@@ -36,7 +36,7 @@ use embedded_graphics::{
     primitives::{Circle, PrimitiveStyle, Rectangle},
     text::Text,
 };
-use lib::{overclock, Pio16BitBus, ILI9488};
+use lib::{overclock, Pio16BitBus, ILI9488, ILI9488_T_WC_NS};
 use overclock::overclock_configs::PLL_SYS_240MHZ;
 
 #[entry]
@@ -88,14 +88,6 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
-    let program = pio_proc::pio_asm!(
-        ".side_set 1"
-        ".wrap_target",
-        "   out pins, 16    side 0",
-        "   nop             side 1",
-        ".wrap"
-    );
-
     let wr: Pin<_, FunctionPio0, _> = pins.gpio19.into_function();
     let wr_pin_id = wr.id().num;
 
@@ -104,62 +96,38 @@ fn main() -> ! {
     let bl = pins.gpio28.into_push_pull_output();
 
     let lcd_d0: Pin<_, FunctionPio0, _> = pins.gpio0.into_function();
-    let lcd_d1: Pin<_, FunctionPio0, _> = pins.gpio1.into_function();
-    let lcd_d2: Pin<_, FunctionPio0, _> = pins.gpio2.into_function();
-    let lcd_d3: Pin<_, FunctionPio0, _> = pins.gpio3.into_function();
-    let lcd_d4: Pin<_, FunctionPio0, _> = pins.gpio4.into_function();
-    let lcd_d5: Pin<_, FunctionPio0, _> = pins.gpio5.into_function();
-    let lcd_d6: Pin<_, FunctionPio0, _> = pins.gpio6.into_function();
-    let lcd_d7: Pin<_, FunctionPio0, _> = pins.gpio7.into_function();
-    let lcd_d8: Pin<_, FunctionPio0, _> = pins.gpio8.into_function();
-    let lcd_d9: Pin<_, FunctionPio0, _> = pins.gpio9.into_function();
-    let lcd_d10: Pin<_, FunctionPio0, _> = pins.gpio10.into_function();
-    let lcd_d11: Pin<_, FunctionPio0, _> = pins.gpio11.into_function();
-    let lcd_d12: Pin<_, FunctionPio0, _> = pins.gpio12.into_function();
-    let lcd_d13: Pin<_, FunctionPio0, _> = pins.gpio13.into_function();
-    let lcd_d14: Pin<_, FunctionPio0, _> = pins.gpio14.into_function();
-    let lcd_d15: Pin<_, FunctionPio0, _> = pins.gpio15.into_function();
+    let _lcd_d1: Pin<_, FunctionPio0, _> = pins.gpio1.into_function();
+    let _lcd_d2: Pin<_, FunctionPio0, _> = pins.gpio2.into_function();
+    let _lcd_d3: Pin<_, FunctionPio0, _> = pins.gpio3.into_function();
+    let _lcd_d4: Pin<_, FunctionPio0, _> = pins.gpio4.into_function();
+    let _lcd_d5: Pin<_, FunctionPio0, _> = pins.gpio5.into_function();
+    let _lcd_d6: Pin<_, FunctionPio0, _> = pins.gpio6.into_function();
+    let _lcd_d7: Pin<_, FunctionPio0, _> = pins.gpio7.into_function();
+    let _lcd_d8: Pin<_, FunctionPio0, _> = pins.gpio8.into_function();
+    let _lcd_d9: Pin<_, FunctionPio0, _> = pins.gpio9.into_function();
+    let _lcd_d10: Pin<_, FunctionPio0, _> = pins.gpio10.into_function();
+    let _lcd_d11: Pin<_, FunctionPio0, _> = pins.gpio11.into_function();
+    let _lcd_d12: Pin<_, FunctionPio0, _> = pins.gpio12.into_function();
+    let _lcd_d13: Pin<_, FunctionPio0, _> = pins.gpio13.into_function();
+    let _lcd_d14: Pin<_, FunctionPio0, _> = pins.gpio14.into_function();
+    let _lcd_d15: Pin<_, FunctionPio0, _> = pins.gpio15.into_function();
 
     let lcd_d0_pin_id = lcd_d0.id().num;
 
-    let pindirs = [
-        (wr_pin_id, hal::pio::PinDir::Output),
-        (lcd_d0.id().num, hal::pio::PinDir::Output),
-        (lcd_d1.id().num, hal::pio::PinDir::Output),
-        (lcd_d2.id().num, hal::pio::PinDir::Output),
-        (lcd_d3.id().num, hal::pio::PinDir::Output),
-        (lcd_d4.id().num, hal::pio::PinDir::Output),
-        (lcd_d5.id().num, hal::pio::PinDir::Output),
-        (lcd_d6.id().num, hal::pio::PinDir::Output),
-        (lcd_d7.id().num, hal::pio::PinDir::Output),
-        (lcd_d8.id().num, hal::pio::PinDir::Output),
-        (lcd_d9.id().num, hal::pio::PinDir::Output),
-        (lcd_d10.id().num, hal::pio::PinDir::Output),
-        (lcd_d11.id().num, hal::pio::PinDir::Output),
-        (lcd_d12.id().num, hal::pio::PinDir::Output),
-        (lcd_d13.id().num, hal::pio::PinDir::Output),
-        (lcd_d14.id().num, hal::pio::PinDir::Output),
-        (lcd_d15.id().num, hal::pio::PinDir::Output),
-    ];
-
     let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-    let installed = pio.install(&program.program).unwrap();
-    let (int, frac) = (1, 0); // as fast as possible
-    let (mut sm, _, tx) = rp2040_hal::pio::PIOBuilder::from_installed_program(installed)
-        .side_set_pin_base(wr_pin_id)
-        .out_pins(lcd_d0_pin_id, 16)
-        .buffers(Buffers::OnlyTx)
-        .clock_divisor_fixed_point(int, frac)
-        .out_shift_direction(ShiftDirection::Right)
-        .autopull(true)
-        .pull_threshold(16)
-        .build(sm0);
-    sm.set_pindirs(pindirs);
-    sm.start();
+    let (di, _sm) = Pio16BitBus::setup(
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+        ILI9488_T_WC_NS,
+        wr_pin_id,
+        lcd_d0_pin_id,
+        dc,
+    )
+    .unwrap();
 
     info!("PIO block setuped");
 
-    let di = Pio16BitBus::new(tx, dc);
     let mut display = ILI9488::new(di, Some(rst), Some(bl), 480, 320);
     display.init(&mut delay).unwrap();
 
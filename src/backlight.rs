@@ -0,0 +1,274 @@
+use display_interface::WriteOnlyDataCommand;
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{self, ErrorKind, OutputPin},
+    pwm::SetDutyCycle,
+};
+
+use crate::{ColorFormat, ILI9488};
+
+/// Backlight driven by a PWM channel, with brightness on a perceptual
+/// scale. Pass it as the `bl` pin of [`ILI9488::new`].
+///
+/// As an [`OutputPin`], high turns the backlight on at the set brightness
+/// and low turns it off, so `init` lights it like a plain pin. PWM errors
+/// show up there as [`ErrorKind::Other`], which `init` returns as
+/// [`InitError::Backlight`](crate::InitError::Backlight).
+pub struct PwmBacklight<P> {
+    pwm: P,
+    brightness: u8,
+}
+
+impl<P: SetDutyCycle> PwmBacklight<P> {
+    /// Starts at full brightness, the duty cycle is left alone until the
+    /// backlight is turned on.
+    pub fn new(pwm: P) -> Self {
+        Self {
+            pwm,
+            brightness: u8::MAX,
+        }
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets the brightness, 0 is off and 255 the full duty cycle.
+    pub fn set_brightness(&mut self, level: u8) -> Result<(), P::Error> {
+        self.brightness = level;
+        let duty = perceptual_duty(level, self.pwm.max_duty_cycle());
+        self.pwm.set_duty_cycle(duty)
+    }
+
+    /// Steps the brightness to `level` one level at a time, spread evenly
+    /// over `duration_ms`.
+    pub fn fade_to(
+        &mut self,
+        level: u8,
+        duration_ms: u32,
+        delay_source: &mut impl DelayNs,
+    ) -> Result<(), P::Error> {
+        let start = self.brightness;
+        let steps = start.abs_diff(level) as u64;
+        let total_us = duration_ms as u64 * 1000;
+        let mut elapsed_us = 0;
+        for step in 1..=steps {
+            let at_us = total_us * step / steps;
+            delay_source.delay_us((at_us - elapsed_us) as u32);
+            elapsed_us = at_us;
+            let next = if level > start {
+                start + step as u8
+            } else {
+                start - step as u8
+            };
+            self.set_brightness(next)?;
+        }
+        Ok(())
+    }
+
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
+impl<P: SetDutyCycle> digital::ErrorType for PwmBacklight<P> {
+    type Error = ErrorKind;
+}
+
+impl<P: SetDutyCycle> OutputPin for PwmBacklight<P> {
+    fn set_low(&mut self) -> Result<(), ErrorKind> {
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(|_| ErrorKind::Other)
+    }
+
+    fn set_high(&mut self) -> Result<(), ErrorKind> {
+        self.set_brightness(self.brightness)
+            .map_err(|_| ErrorKind::Other)
+    }
+}
+
+/// Duty cycle that looks `level`/255 as bright, following the CIE 1931
+/// lightness curve. Any level above 0 keeps the backlight on.
+pub fn perceptual_duty(level: u8, max_duty: u16) -> u16 {
+    if level == 0 {
+        return 0;
+    }
+    let (level, max_duty) = (level as u64, max_duty as u64);
+    // Lightness L = 100 * level / 255, luminance is L / 903.3 up to L = 8
+    // and ((L + 16) / 116)^3 above.
+    let duty = if level * 100 <= 8 * 255 {
+        max_duty * level * 1000 / (255 * 9033)
+    } else {
+        let num = level * 100 + 16 * 255;
+        let den = 116 * 255;
+        max_duty * num * num * num / (den * den * den)
+    };
+    (duty as u16).max(1)
+}
+
+impl<DI, RST, P, C, TE> ILI9488<DI, RST, PwmBacklight<P>, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    P: SetDutyCycle,
+    C: ColorFormat,
+{
+    pub fn backlight_mut(&mut self) -> Option<&mut PwmBacklight<P>> {
+        self.bl.as_mut()
+    }
+
+    /// See [`PwmBacklight::set_brightness`], does nothing without a
+    /// backlight.
+    pub fn set_brightness(&mut self, level: u8) -> Result<(), P::Error> {
+        match self.bl.as_mut() {
+            Some(bl) => bl.set_brightness(level),
+            None => Ok(()),
+        }
+    }
+
+    /// See [`PwmBacklight::fade_to`], does nothing without a backlight.
+    pub fn fade_brightness(
+        &mut self,
+        level: u8,
+        duration_ms: u32,
+        delay_source: &mut impl DelayNs,
+    ) -> Result<(), P::Error> {
+        match self.bl.as_mut() {
+            Some(bl) => bl.fade_to(level, duration_ms, delay_source),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockInterface, NoopPin};
+    use crate::InitError;
+    use core::convert::Infallible;
+    use embedded_hal::pwm;
+
+    /// PWM channel that remembers every duty cycle it was set to.
+    struct MockPwm {
+        max: u16,
+        duties: Vec<u16>,
+    }
+
+    impl MockPwm {
+        fn new(max: u16) -> Self {
+            Self {
+                max,
+                duties: Vec::new(),
+            }
+        }
+    }
+
+    impl pwm::ErrorType for MockPwm {
+        type Error = Infallible;
+    }
+
+    impl SetDutyCycle for MockPwm {
+        fn max_duty_cycle(&self) -> u16 {
+            self.max
+        }
+
+        fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+            self.duties.push(duty);
+            Ok(())
+        }
+    }
+
+    /// PWM channel that fails every write.
+    struct BrokenPwm;
+
+    impl pwm::ErrorType for BrokenPwm {
+        type Error = pwm::ErrorKind;
+    }
+
+    impl SetDutyCycle for BrokenPwm {
+        fn max_duty_cycle(&self) -> u16 {
+            1000
+        }
+
+        fn set_duty_cycle(&mut self, _duty: u16) -> Result<(), pwm::ErrorKind> {
+            Err(pwm::ErrorKind::Other)
+        }
+    }
+
+    type Display = ILI9488<MockInterface, NoopPin, PwmBacklight<MockPwm>>;
+
+    fn display(max: u16) -> Display {
+        let bl = PwmBacklight::new(MockPwm::new(max));
+        ILI9488::new(MockInterface::new(), None, Some(bl), 480, 320)
+    }
+
+    fn duties(display: &mut Display) -> &mut Vec<u16> {
+        &mut display.backlight_mut().unwrap().pwm.duties
+    }
+
+    #[test]
+    fn curve_ends_and_shape() {
+        assert_eq!(perceptual_duty(0, 65535), 0);
+        assert_eq!(perceptual_duty(255, 65535), 65535);
+        assert_eq!(perceptual_duty(255, 1000), 1000);
+        // Half the lightness is under a fifth of the light.
+        assert_eq!(perceptual_duty(128, 1000), 185);
+        // The dimmest levels still light up, even on a coarse PWM.
+        assert_eq!(perceptual_duty(1, 255), 1);
+
+        let mut last = 0;
+        for level in 1..=255 {
+            let duty = perceptual_duty(level, 65535);
+            assert!(duty > last, "level {level}");
+            last = duty;
+        }
+    }
+
+    #[test]
+    fn init_lights_at_the_set_brightness() {
+        let mut display = display(1000);
+        display.set_brightness(128).unwrap();
+        display.backlight_mut().unwrap().set_low().unwrap();
+        display.init(&mut MockDelay::default()).unwrap();
+        assert_eq!(*duties(&mut display), [185, 0, 185]);
+        assert_eq!(display.backlight_mut().unwrap().brightness(), 128);
+    }
+
+    #[test]
+    fn init_returns_pwm_errors() {
+        let bl = PwmBacklight::new(BrokenPwm);
+        let mut display: ILI9488<_, NoopPin, _> =
+            ILI9488::new(MockInterface::new(), None, Some(bl), 480, 320);
+        let error = display.init(&mut MockDelay::default()).unwrap_err();
+        assert!(matches!(error, InitError::Backlight(ErrorKind::Other)));
+    }
+
+    #[test]
+    fn fade_steps_every_level_in_time() {
+        let mut display = display(65535);
+        display.set_brightness(10).unwrap();
+        duties(&mut display).clear();
+
+        let mut delay = MockDelay::default();
+        display.fade_brightness(0, 300, &mut delay).unwrap();
+        assert_eq!(delay.total_ns, 300_000_000);
+        let expected: Vec<u16> = (0..10).rev().map(|l| perceptual_duty(l, 65535)).collect();
+        assert_eq!(*duties(&mut display), expected);
+
+        duties(&mut display).clear();
+        display.fade_brightness(255, 1000, &mut delay).unwrap();
+        assert_eq!(duties(&mut display).len(), 255);
+        assert_eq!(duties(&mut display).last(), Some(&65535));
+        assert_eq!(delay.total_ns, 1_300_000_000);
+    }
+
+    #[test]
+    fn no_backlight_no_fade() {
+        let mut display: Display = ILI9488::new(MockInterface::new(), None, None, 480, 320);
+        let mut delay = MockDelay::default();
+        display.set_brightness(10).unwrap();
+        display.fade_brightness(200, 100, &mut delay).unwrap();
+        assert_eq!(delay.total_ns, 0);
+    }
+}
@@ -0,0 +1,410 @@
+use core::marker::PhantomData;
+use display_interface::WriteOnlyDataCommand;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+
+use crate::{BusPixelFormat, ColorFormat, InitError, Orientation, PixelFormat, ILI9488};
+
+/// A command sent as is during init: `(cmd, params, delay in ms after it)`.
+pub type RawCommand<'a> = (u8, &'a [u8], u32);
+
+/// Positive (0xE0) and negative (0xE1) gamma correction, 15 parameters
+/// each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GammaCurve {
+    pub positive: [u8; 15],
+    pub negative: [u8; 15],
+}
+
+impl GammaCurve {
+    /// The curve the driver has always used.
+    pub const DEFAULT: Self = Self {
+        positive: [
+            0x00, 0x03, 0x09, 0x08, 0x16, 0x0A, 0x3F, 0x78, 0x4C, 0x09, 0x0A, 0x08, 0x16, 0x1A,
+            0x0F,
+        ],
+        negative: [
+            0x00, 0x16, 0x19, 0x03, 0x0F, 0x05, 0x32, 0x45, 0x46, 0x04, 0x0E, 0x0D, 0x35, 0x37,
+            0x0F,
+        ],
+    };
+}
+
+/// Power Control 1 (0xC0) and 2 (0xC1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerControl {
+    /// VREG1OUT, the positive gamma reference.
+    pub vreg1out: u8,
+    /// VREG2OUT, the negative gamma reference.
+    pub vreg2out: u8,
+    /// Step-up factor of the gate driver supply.
+    pub step_up: u8,
+}
+
+impl PowerControl {
+    pub const DEFAULT: Self = Self {
+        vreg1out: 0x17,
+        vreg2out: 0x15,
+        step_up: 0x41,
+    };
+}
+
+/// VCOM Control (0xC5).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Vcom {
+    /// VCM_REG, the VCOM voltage.
+    pub level: u8,
+    /// Take VCOM from `level` instead of the NV memory.
+    pub from_register: bool,
+}
+
+impl Vcom {
+    pub const DEFAULT: Self = Self {
+        level: 0x12,
+        from_register: true,
+    };
+
+    pub(crate) fn nv_memory_bit(self) -> u8 {
+        if self.from_register {
+            0x80
+        } else {
+            0x00
+        }
+    }
+}
+
+/// Frame Rate Control in normal mode (0xB1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRate {
+    /// FRS, the frame rate select field.
+    pub frs: u8,
+    /// DIVA, division ratio of the internal clock.
+    pub diva: u8,
+    /// RTNA, clocks per line.
+    pub rtna: u8,
+}
+
+impl FrameRate {
+    pub const HZ_60: Self = Self {
+        frs: 0xD,
+        diva: 0,
+        rtna: 0x11,
+    };
+    pub const HZ_90: Self = Self {
+        frs: 0xD,
+        diva: 0,
+        rtna: 0x14,
+    };
+
+    pub(crate) fn frs_diva(self) -> u8 {
+        (self.frs & 0xF) << 4 | self.diva & 0x3
+    }
+}
+
+/// Display Inversion Control (0xB4), how the source drivers alternate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inversion {
+    Column = 0x00,
+    OneDot = 0x01,
+    TwoDot = 0x02,
+}
+
+/// Entry Mode Set (0xB7).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMode {
+    /// EPF, how 16-bit pixels are expanded to 18 bits (0..=3).
+    pub pixel_expansion: u8,
+    /// GON/DTE, gate output control (0..=3).
+    pub gate_output: u8,
+    pub low_voltage_detection: bool,
+}
+
+impl EntryMode {
+    pub const DEFAULT: Self = Self {
+        pixel_expansion: 3,
+        gate_output: 3,
+        low_voltage_detection: true,
+    };
+
+    pub(crate) fn bits(self) -> u8 {
+        (self.pixel_expansion & 0x3) << 6
+            | (self.gate_output & 0x3) << 1
+            | !self.low_voltage_detection as u8
+    }
+}
+
+/// Register values written by `init`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InitRegisters {
+    pub gamma: GammaCurve,
+    pub power: PowerControl,
+    pub vcom: Vcom,
+    pub frame_rate: FrameRate,
+    pub inversion: Inversion,
+    pub entry_mode: EntryMode,
+    pub invert_colors: bool,
+}
+
+impl Default for InitRegisters {
+    fn default() -> Self {
+        Self {
+            gamma: GammaCurve::DEFAULT,
+            power: PowerControl::DEFAULT,
+            vcom: Vcom::DEFAULT,
+            frame_rate: FrameRate::HZ_90,
+            inversion: Inversion::TwoDot,
+            entry_mode: EntryMode::DEFAULT,
+            invert_colors: false,
+        }
+    }
+}
+
+/// Sets up and initializes an [`ILI9488`] with other register values than
+/// the ones [`ILI9488::init`] uses.
+///
+/// ```ignore
+/// let display = ILI9488Builder::new(di, Some(rst), Some(bl))
+///     .frame_rate(FrameRate::HZ_60)
+///     .vcom(Vcom { level: 0x18, from_register: true })
+///     .raw_commands(&[(0xE9, &[0x00], 0)])
+///     .init(&mut delay)?;
+/// ```
+pub struct ILI9488Builder<'a, DI, RST, BL, C = Rgb565> {
+    di: DI,
+    rst: Option<RST>,
+    bl: Option<BL>,
+    size: (u16, u16),
+    pixel_format: PixelFormat,
+    orientation: Orientation,
+    regs: InitRegisters,
+    raw: &'a [RawCommand<'a>],
+    color: PhantomData<C>,
+}
+
+impl<'a, DI, RST, BL> ILI9488Builder<'a, DI, RST, BL>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+{
+    /// Starts from the same settings as [`ILI9488::new`] followed by
+    /// [`ILI9488::init`], on a 480x320 panel.
+    pub fn new(di: DI, rst: Option<RST>, bl: Option<BL>) -> Self {
+        Self {
+            di,
+            rst,
+            bl,
+            size: (480, 320),
+            pixel_format: PixelFormat::Rgb565,
+            orientation: Orientation::default(),
+            regs: InitRegisters::default(),
+            raw: &[],
+            color: PhantomData,
+        }
+    }
+
+    /// See [`ILI9488::with_pixel_format`].
+    pub fn pixel_format(mut self, pixel_format: PixelFormat) -> Self {
+        self.pixel_format = pixel_format;
+        self
+    }
+}
+
+impl<'a, DI, RST, BL, C> ILI9488Builder<'a, DI, RST, BL, C>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Colour type of the display, see [`ILI9488::with_color_format`].
+    pub fn color_format<C2: ColorFormat>(self) -> ILI9488Builder<'a, DI, RST, BL, C2> {
+        ILI9488Builder {
+            di: self.di,
+            rst: self.rst,
+            bl: self.bl,
+            size: self.size,
+            pixel_format: C2::PIXEL_FORMAT,
+            orientation: self.orientation,
+            regs: self.regs,
+            raw: self.raw,
+            color: PhantomData,
+        }
+    }
+
+    /// Resolution in landscape orientation.
+    pub fn size(mut self, width: u16, height: u16) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: GammaCurve) -> Self {
+        self.regs.gamma = gamma;
+        self
+    }
+
+    pub fn power_control(mut self, power: PowerControl) -> Self {
+        self.regs.power = power;
+        self
+    }
+
+    pub fn vcom(mut self, vcom: Vcom) -> Self {
+        self.regs.vcom = vcom;
+        self
+    }
+
+    pub fn frame_rate(mut self, frame_rate: FrameRate) -> Self {
+        self.regs.frame_rate = frame_rate;
+        self
+    }
+
+    pub fn inversion(mut self, inversion: Inversion) -> Self {
+        self.regs.inversion = inversion;
+        self
+    }
+
+    pub fn entry_mode(mut self, entry_mode: EntryMode) -> Self {
+        self.regs.entry_mode = entry_mode;
+        self
+    }
+
+    /// Turns on display inversion (0x21), which some IPS batches need to
+    /// show colours right.
+    pub fn invert_colors(mut self, invert: bool) -> Self {
+        self.regs.invert_colors = invert;
+        self
+    }
+
+    /// Commands sent after the register setup, right before sleep out.
+    pub fn raw_commands(mut self, raw: &'a [RawCommand<'a>]) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    /// Resets and initializes the panel.
+    pub fn init(
+        self,
+        delay_source: &mut impl DelayNs,
+    ) -> Result<ILI9488<DI, RST, BL, C>, InitError<BL::Error>>
+    where
+        DI: BusPixelFormat,
+    {
+        let (width, height) = self.size;
+        let mut display =
+            ILI9488::new(self.di, self.rst, self.bl, width, height).with_color_format::<C>();
+        display.pixel_format = self.pixel_format;
+        if !self.orientation.is_landscape() {
+            core::mem::swap(&mut display.size_x, &mut display.size_y);
+        }
+        display.orientation = self.orientation;
+        display.init_with(&self.regs, self.raw, delay_source)?;
+        Ok(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockInterface, NoopPin};
+    use embedded_graphics::prelude::{OriginDimensions, Size};
+
+    fn builder<'a>() -> ILI9488Builder<'a, MockInterface, NoopPin, NoopPin> {
+        ILI9488Builder::new(MockInterface::new(), None, None)
+    }
+
+    #[test]
+    fn defaults_match_init() {
+        let display = builder().init(&mut MockDelay::default()).unwrap();
+
+        let mut plain: ILI9488<_, NoopPin, NoopPin> =
+            ILI9488::new(MockInterface::new(), None, None, 480, 320);
+        plain.init(&mut MockDelay::default()).unwrap();
+
+        assert_eq!(display.di.opcodes(), plain.di.opcodes());
+        display
+            .di
+            .expect()
+            .command(0xE0, &GammaCurve::DEFAULT.positive)
+            .command(0xE1, &GammaCurve::DEFAULT.negative)
+            .command(0xC0, &[0x17, 0x15])
+            .command(0xC1, &[0x41])
+            .command(0xC5, &[0x00, 0x12, 0x80])
+            .skip_to(0xB1)
+            .command(0xB1, &[0xD0, 0x14])
+            .command(0xB4, &[0x02])
+            .skip_to(0xB7)
+            .command(0xB7, &[0xC6]);
+    }
+
+    #[test]
+    fn typed_setters() {
+        let display = builder()
+            .power_control(PowerControl {
+                vreg1out: 0x10,
+                vreg2out: 0x11,
+                step_up: 0x44,
+            })
+            .vcom(Vcom {
+                level: 0x20,
+                from_register: false,
+            })
+            .frame_rate(FrameRate::HZ_60)
+            .inversion(Inversion::Column)
+            .entry_mode(EntryMode {
+                pixel_expansion: 0,
+                gate_output: 3,
+                low_voltage_detection: false,
+            })
+            .invert_colors(true)
+            .init(&mut MockDelay::default())
+            .unwrap();
+
+        let di = &display.di;
+        assert_eq!(di.last(0xC0).unwrap().data, [0x10, 0x11]);
+        assert_eq!(di.last(0xC1).unwrap().data, [0x44]);
+        assert_eq!(di.last(0xC5).unwrap().data, [0x00, 0x20, 0x00]);
+        assert_eq!(di.last(0xB1).unwrap().data, [0xD0, 0x11]);
+        assert_eq!(di.last(0xB4).unwrap().data, [0x00]);
+        assert_eq!(di.last(0xB7).unwrap().data, [0x07]);
+        assert!(di.last(0x21).is_some());
+    }
+
+    #[test]
+    fn raw_commands_before_sleep_out() {
+        let mut delay = MockDelay::default();
+        let display = builder()
+            .raw_commands(&[(0xE9, &[0x01], 10), (0xFB, &[0x3C, 0x00], 0)])
+            .init(&mut delay)
+            .unwrap();
+
+        display
+            .di
+            .expect()
+            .skip_to(0xF7)
+            .command(0xF7, &[0xA9, 0x51, 0x2C, 0x82])
+            .command(0xE9, &[0x01])
+            .command(0xFB, &[0x3C, 0x00])
+            .command(0x11, &[])
+            .command(0x29, &[])
+            .end();
+        assert_eq!(delay.total_ns, 70_000_000);
+    }
+
+    #[test]
+    fn portrait_swaps_size() {
+        let display = builder()
+            .orientation(Orientation::Portrait(false))
+            .pixel_format(PixelFormat::Rgb666)
+            .init(&mut MockDelay::default())
+            .unwrap();
+        assert_eq!(display.size(), Size::new(320, 480));
+        assert_eq!(display.orientation(), Orientation::Portrait(false));
+        assert_eq!(display.di.last(0x36).unwrap().data, [0x48]);
+        assert_eq!(display.di.last(0x3A).unwrap().data, [0x66]);
+    }
+}
@@ -0,0 +1,180 @@
+use display_interface::{DataFormat, WriteOnlyDataCommand};
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb666, Rgb888},
+    prelude::{IntoStorage, PixelColor, RgbColor},
+};
+
+use crate::{PixelFormat, RepeatDataCommand, Result};
+
+/// Colour type an [`ILI9488`](crate::ILI9488) can draw with, and how its
+/// pixels are put on the bus.
+pub trait ColorFormat: PixelColor {
+    /// Pixel format `init` programs for this colour type.
+    const PIXEL_FORMAT: PixelFormat;
+
+    /// Sends `colors` as GRAM data, following a RAMWR.
+    fn send_pixels<DI>(di: &mut DI, colors: impl Iterator<Item = Self>) -> Result
+    where
+        DI: WriteOnlyDataCommand;
+
+    /// Sends `color` `count` times as GRAM data, following a RAMWR.
+    fn send_repeated<DI>(di: &mut DI, color: Self, count: u32) -> Result
+    where
+        DI: RepeatDataCommand;
+}
+
+impl ColorFormat for Rgb565 {
+    const PIXEL_FORMAT: PixelFormat = PixelFormat::Rgb565;
+
+    fn send_pixels<DI>(di: &mut DI, colors: impl Iterator<Item = Self>) -> Result
+    where
+        DI: WriteOnlyDataCommand,
+    {
+        let mut iter = colors.map(|c| c.into_storage());
+        di.send_data(DataFormat::U16BEIter(&mut iter))
+    }
+
+    fn send_repeated<DI>(di: &mut DI, color: Self, count: u32) -> Result
+    where
+        DI: RepeatDataCommand,
+    {
+        di.send_repeated(DataFormat::U16BE(&mut [color.into_storage()]), count)
+    }
+}
+
+/// In 18-bit mode every channel takes a byte, left aligned; the controller
+/// ignores the two low bits.
+impl ColorFormat for Rgb666 {
+    const PIXEL_FORMAT: PixelFormat = PixelFormat::Rgb666;
+
+    fn send_pixels<DI>(di: &mut DI, colors: impl Iterator<Item = Self>) -> Result
+    where
+        DI: WriteOnlyDataCommand,
+    {
+        let mut iter = colors.flat_map(|c| [c.r() << 2, c.g() << 2, c.b() << 2]);
+        di.send_data(DataFormat::U8Iter(&mut iter))
+    }
+
+    fn send_repeated<DI>(di: &mut DI, color: Self, count: u32) -> Result
+    where
+        DI: RepeatDataCommand,
+    {
+        let bytes = [color.r() << 2, color.g() << 2, color.b() << 2];
+        di.send_repeated(DataFormat::U8(&bytes), count)
+    }
+}
+
+/// Sent as is in 18-bit mode, the controller drops the two low bits of
+/// each channel.
+impl ColorFormat for Rgb888 {
+    const PIXEL_FORMAT: PixelFormat = PixelFormat::Rgb666;
+
+    fn send_pixels<DI>(di: &mut DI, colors: impl Iterator<Item = Self>) -> Result
+    where
+        DI: WriteOnlyDataCommand,
+    {
+        let mut iter = colors.flat_map(|c| [c.r(), c.g(), c.b()]);
+        di.send_data(DataFormat::U8Iter(&mut iter))
+    }
+
+    fn send_repeated<DI>(di: &mut DI, color: Self, count: u32) -> Result
+    where
+        DI: RepeatDataCommand,
+    {
+        di.send_repeated(DataFormat::U8(&[color.r(), color.g(), color.b()]), count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::mock::{MockDelay, MockInterface, NoopPin};
+    use crate::ILI9488;
+    use embedded_graphics::{
+        prelude::{DrawTarget, Point, Primitive, Size},
+        primitives::{PrimitiveStyle, Rectangle},
+        Drawable, Pixel,
+    };
+
+    fn display<C: ColorFormat>() -> ILI9488<MockInterface, NoopPin, NoopPin, C> {
+        ILI9488::new(MockInterface::new(), None, None, 480, 320).with_color_format()
+    }
+
+    #[test]
+    fn init_programs_18bit_for_wide_colors() {
+        let mut rgb666 = display::<Rgb666>();
+        rgb666.init(&mut MockDelay::default()).unwrap();
+        assert_eq!(rgb666.di.last(0x3A).unwrap().data, [0x66]);
+
+        let mut rgb888 = display::<Rgb888>();
+        rgb888.init(&mut MockDelay::default()).unwrap();
+        assert_eq!(rgb888.di.last(0x3A).unwrap().data, [0x66]);
+    }
+
+    #[test]
+    fn rgb666_is_left_aligned() {
+        let mut display = display::<Rgb666>();
+        let pixels = [Rgb666::new(0x3F, 0x20, 0x01), Rgb666::new(0, 0x3F, 0)];
+        display
+            .fill_contiguous(&Rectangle::new(Point::zero(), Size::new(2, 1)), pixels)
+            .unwrap();
+
+        display
+            .di
+            .expect()
+            .window(0, 0, 1, 0)
+            .command(0x2C, &[0xFC, 0x80, 0x04, 0x00, 0xFC, 0x00])
+            .end();
+    }
+
+    #[test]
+    fn repeated_rgb666() {
+        let mut display = display::<Rgb666>();
+        let area = Rectangle::new(Point::zero(), Size::new(2, 1));
+        display
+            .fill_repeated(&area, Rgb666::new(0x3F, 0x20, 0x01))
+            .unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 1, 0)
+            .command(0x2C, &[0xFC, 0x80, 0x04, 0xFC, 0x80, 0x04])
+            .end();
+    }
+
+    #[test]
+    fn rgb888_goes_out_unchanged() {
+        let mut display = display::<Rgb888>();
+        display
+            .draw_iter([Pixel(Point::new(3, 4), Rgb888::new(0x12, 0x34, 0x56))])
+            .unwrap();
+
+        display
+            .di
+            .expect()
+            .window(3, 4, 3, 4)
+            .command(0x2C, &[0x12, 0x34, 0x56])
+            .end();
+    }
+
+    #[test]
+    fn gradient_keeps_18bit_depth() {
+        let mut display: ILI9488<_, NoopPin, NoopPin, Rgb888> =
+            ILI9488::new(Emulator::new(), None, None, 480, 320).with_color_format();
+        display.init(&mut MockDelay::default()).unwrap();
+        for (x, level) in (0..64).map(|i| (i, i as u8 * 4)) {
+            Rectangle::new(Point::new(x, 0), Size::new(1, 10))
+                .into_styled(PrimitiveStyle::with_fill(Rgb888::new(level, 0, 0)))
+                .draw(&mut display)
+                .unwrap();
+        }
+
+        // Every one of the 64 steps is a level of its own, Rgb565 would
+        // have merged them in pairs.
+        let emu = display.di_mut();
+        for x in 0..64 {
+            assert_eq!(emu.pixel(x, 5)[0] >> 2, x as u8);
+        }
+    }
+}
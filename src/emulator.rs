@@ -0,0 +1,554 @@
+//! Software model of the ILI9488 for host tests.
+//!
+//! [`Emulator`] takes the same [`WriteOnlyDataCommand`] calls as a real
+//! bus and runs them against an 18-bit GRAM, so tests can check the image
+//! a command stream produces instead of the commands themselves.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+
+use crate::BusPixelFormat;
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// Source lines (columns) of the panel.
+const PANEL_COLS: usize = 320;
+/// Gate lines (rows) of the panel.
+const PANEL_ROWS: usize = 480;
+
+/// Width and height of the dumped image, see [`Emulator::rgb888`].
+pub const WIDTH: usize = PANEL_ROWS;
+pub const HEIGHT: usize = PANEL_COLS;
+
+const MADCTL_MY: u8 = 0x80;
+const MADCTL_MX: u8 = 0x40;
+const MADCTL_MV: u8 = 0x20;
+const MADCTL_BGR: u8 = 0x08;
+
+/// The module on this board has a BGR colour filter, so colours come out
+/// as sent only with MADCTL.BGR set.
+const PANEL_BGR: bool = true;
+
+/// ILI9488 driven over an 8-bit DBI bus.
+///
+/// 16-bit data is split into bytes the way [`crate::Pio8BitBus`] sends
+/// it. Commands the model doesn't know are accepted and ignored.
+pub struct Emulator {
+    /// 6-bit R, G, B per pixel, indexed by `row * PANEL_COLS + col`.
+    gram: Vec<[u8; 3]>,
+    madctl: u8,
+    colmod: u8,
+    inverted: bool,
+    /// Column and page address ranges, inclusive.
+    columns: (u16, u16),
+    pages: (u16, u16),
+    /// Write position in MCU address space.
+    cursor: (u16, u16),
+    cmd: Option<u8>,
+    params: Vec<u8>,
+    /// Bytes of a pixel that hasn't been completed yet.
+    partial: Vec<u8>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator {
+    /// GRAM is black, the registers hold their reset values.
+    pub fn new() -> Self {
+        let mut emulator = Self {
+            gram: vec![[0; 3]; PANEL_COLS * PANEL_ROWS],
+            madctl: 0,
+            colmod: 0,
+            inverted: false,
+            columns: (0, 0),
+            pages: (0, 0),
+            cursor: (0, 0),
+            cmd: None,
+            params: Vec::new(),
+            partial: Vec::new(),
+        };
+        emulator.reset();
+        emulator
+    }
+
+    fn reset(&mut self) {
+        self.madctl = 0;
+        self.colmod = 0x66;
+        self.inverted = false;
+        self.columns = (0, PANEL_COLS as u16 - 1);
+        self.pages = (0, PANEL_ROWS as u16 - 1);
+    }
+
+    pub fn madctl(&self) -> u8 {
+        self.madctl
+    }
+
+    pub fn colmod(&self) -> u8 {
+        self.colmod
+    }
+
+    fn exchanged(&self) -> bool {
+        self.madctl & MADCTL_MV != 0
+    }
+
+    /// Last column and page address of the current orientation.
+    fn address_limits(&self) -> (u16, u16) {
+        if self.exchanged() {
+            (PANEL_ROWS as u16 - 1, PANEL_COLS as u16 - 1)
+        } else {
+            (PANEL_COLS as u16 - 1, PANEL_ROWS as u16 - 1)
+        }
+    }
+
+    /// Maps a column/page address to a panel `(col, row)`.
+    fn physical(&self, column: u16, page: u16) -> Option<(usize, usize)> {
+        let (max_col, max_page) = self.address_limits();
+        if column > max_col || page > max_page {
+            return None;
+        }
+        let column = if self.madctl & MADCTL_MX != 0 {
+            max_col - column
+        } else {
+            column
+        };
+        let page = if self.madctl & MADCTL_MY != 0 {
+            max_page - page
+        } else {
+            page
+        };
+        let (col, row) = if self.exchanged() {
+            (page, column)
+        } else {
+            (column, page)
+        };
+        Some((col as usize, row as usize))
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        match self.colmod & 0x07 {
+            0x05 => 2,
+            _ => 3,
+        }
+    }
+
+    fn decode_pixel(&self, bytes: &[u8]) -> [u8; 3] {
+        if let [hi, lo] = *bytes {
+            let value = u16::from_be_bytes([hi, lo]);
+            let r = (value >> 11) as u8 & 0x1F;
+            let g = (value >> 5) as u8 & 0x3F;
+            let b = value as u8 & 0x1F;
+            // 5-bit channels get their MSB repeated as LSB.
+            [r << 1 | r >> 4, g, b << 1 | b >> 4]
+        } else {
+            [bytes[0] >> 2, bytes[1] >> 2, bytes[2] >> 2]
+        }
+    }
+
+    fn write_pixel(&mut self, rgb: [u8; 3]) {
+        let (column, page) = self.cursor;
+        if let Some((col, row)) = self.physical(column, page) {
+            self.gram[row * PANEL_COLS + col] = rgb;
+        }
+
+        // Columns first, then pages, wrapping back to the window start.
+        if column < self.columns.1 {
+            self.cursor.0 += 1;
+        } else {
+            self.cursor.0 = self.columns.0;
+            self.cursor.1 = if page < self.pages.1 {
+                page + 1
+            } else {
+                self.pages.0
+            };
+        }
+    }
+
+    fn command(&mut self, cmd: u8) {
+        self.cmd = Some(cmd);
+        self.params.clear();
+        self.partial.clear();
+        match cmd {
+            0x01 => self.reset(),
+            0x20 => self.inverted = false,
+            0x21 => self.inverted = true,
+            0x2C => self.cursor = (self.columns.0, self.pages.0),
+            _ => {}
+        }
+    }
+
+    fn data(&mut self, byte: u8) {
+        let Some(cmd) = self.cmd else {
+            return;
+        };
+        if cmd == 0x2C || cmd == 0x3C {
+            self.partial.push(byte);
+            if self.partial.len() == self.bytes_per_pixel() {
+                let rgb = self.decode_pixel(&self.partial);
+                self.partial.clear();
+                self.write_pixel(rgb);
+            }
+            return;
+        }
+
+        self.params.push(byte);
+        let p = &self.params;
+        let range = || {
+            let start = u16::from_be_bytes([p[0], p[1]]);
+            (start, u16::from_be_bytes([p[2], p[3]]))
+        };
+        match (cmd, p.len()) {
+            (0x2A, 4) => self.columns = range(),
+            (0x2B, 4) => self.pages = range(),
+            (0x36, 1) => self.madctl = byte,
+            (0x3A, 1) => self.colmod = byte,
+            _ => {}
+        }
+    }
+
+    fn data_iter(&mut self, bytes: impl Iterator<Item = u8>) {
+        bytes.for_each(|byte| self.data(byte));
+    }
+
+    /// Colour of panel pixel `(col, row)` as it is displayed.
+    fn shown(&self, col: usize, row: usize) -> [u8; 3] {
+        let [mut r, g, mut b] = self.gram[row * PANEL_COLS + col];
+        if (self.madctl & MADCTL_BGR != 0) != PANEL_BGR {
+            core::mem::swap(&mut r, &mut b);
+        }
+        let expand = |v: u8| {
+            let v = if self.inverted { !v & 0x3F } else { v };
+            v << 2 | v >> 4
+        };
+        [expand(r), expand(g), expand(b)]
+    }
+
+    /// The panel content as 8-bit RGB, [`WIDTH`] x [`HEIGHT`], row-major.
+    ///
+    /// The image is laid out like the landscape orientation `ILI9488::init`
+    /// selects (MADCTL 0x28): pixel `(x, y)` is at column `x`, page `y`.
+    pub fn rgb888(&self) -> Vec<u8> {
+        let mut image = Vec::with_capacity(WIDTH * HEIGHT * 3);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                image.extend(self.shown(y, x));
+            }
+        }
+        image
+    }
+
+    /// Displayed 8-bit RGB colour at `(x, y)` of [`Self::rgb888`].
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.shown(y, x)
+    }
+
+    pub fn write_ppm(&self, mut w: impl Write) -> std::io::Result<()> {
+        write!(w, "P6\n{WIDTH} {HEIGHT}\n255\n")?;
+        w.write_all(&self.rgb888())
+    }
+
+    pub fn write_png(&self, w: impl Write) -> std::io::Result<()> {
+        let mut encoder = png::Encoder::new(w, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb888())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Compares the panel with `tests/golden/<name>.png`.
+    ///
+    /// On a mismatch the actual image is written next to the target dir for
+    /// inspection. Run with `UPDATE_GOLDEN=1` to (re)create the reference.
+    #[track_caller]
+    pub fn assert_golden(&self, name: &str) {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let golden = root.join("tests/golden").join(format!("{name}.png"));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            self.write_png(std::fs::File::create(&golden).unwrap())
+                .unwrap();
+            return;
+        }
+
+        let expected = std::fs::File::open(&golden)
+            .ok()
+            .and_then(|file| read_png(file).ok());
+        let actual = self.rgb888();
+        if expected.as_ref() == Some(&actual) {
+            return;
+        }
+
+        let dump = root.join("target/golden").join(format!("{name}.png"));
+        std::fs::create_dir_all(dump.parent().unwrap()).unwrap();
+        self.write_png(std::fs::File::create(&dump).unwrap())
+            .unwrap();
+        match expected {
+            None => panic!(
+                "no golden image {}, actual output in {}",
+                golden.display(),
+                dump.display()
+            ),
+            Some(expected) => {
+                let diff = expected
+                    .chunks(3)
+                    .zip(actual.chunks(3))
+                    .enumerate()
+                    .filter(|(_, (e, a))| e != a)
+                    .map(|(i, _)| (i % WIDTH, i / WIDTH))
+                    .collect::<Vec<_>>();
+                panic!(
+                    "{name}: {} pixels differ, first at {:?}, actual output in {}",
+                    diff.len(),
+                    diff.first(),
+                    dump.display()
+                );
+            }
+        }
+    }
+}
+
+fn read_png(r: impl std::io::Read) -> core::result::Result<Vec<u8>, png::DecodingError> {
+    let mut reader = png::Decoder::new(r).read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    Ok(buf)
+}
+
+impl BusPixelFormat for Emulator {}
+
+impl WriteOnlyDataCommand for Emulator {
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        let DataFormat::U8(bytes) = cmd else {
+            return Err(DisplayError::DataFormatNotImplemented);
+        };
+        for &cmd in bytes {
+            self.command(cmd);
+        }
+        Ok(())
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        match buf {
+            DataFormat::U8(slice) => self.data_iter(slice.iter().copied()),
+            DataFormat::U8Iter(iter) => self.data_iter(iter),
+            DataFormat::U16(slice) => self.data_iter(slice.iter().flat_map(|v| v.to_ne_bytes())),
+            DataFormat::U16BE(slice) => self.data_iter(slice.iter().flat_map(|v| v.to_be_bytes())),
+            DataFormat::U16LE(slice) => self.data_iter(slice.iter().flat_map(|v| v.to_le_bytes())),
+            DataFormat::U16BEIter(iter) => self.data_iter(iter.flat_map(u16::to_be_bytes)),
+            DataFormat::U16LEIter(iter) => self.data_iter(iter.flat_map(u16::to_le_bytes)),
+            _ => return Err(DisplayError::DataFormatNotImplemented),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, NoopPin};
+    use crate::ILI9488;
+    use embedded_graphics::{
+        mono_font::{ascii::FONT_10X20, MonoTextStyle},
+        pixelcolor::Rgb565,
+        prelude::*,
+        primitives::{
+            Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StrokeAlignment,
+            Triangle,
+        },
+        text::Text,
+    };
+
+    const RED: [u8; 3] = [0xFF, 0x00, 0x00];
+    const BLUE: [u8; 3] = [0x00, 0x00, 0xFF];
+    const BLACK: [u8; 3] = [0x00; 3];
+
+    fn send(emu: &mut Emulator, cmd: u8, params: &[u8]) {
+        emu.send_commands(DataFormat::U8(&[cmd])).unwrap();
+        emu.send_data(DataFormat::U8(params)).unwrap();
+    }
+
+    fn landscape() -> Emulator {
+        let mut emu = Emulator::new();
+        send(&mut emu, 0x36, &[0x28]);
+        send(&mut emu, 0x3A, &[0x55]);
+        emu
+    }
+
+    fn fill(emu: &mut Emulator, xs: u16, ys: u16, xe: u16, ye: u16, color: Rgb565, n: usize) {
+        send(emu, 0x2A, &range(xs, xe));
+        send(emu, 0x2B, &range(ys, ye));
+        emu.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        let mut iter = core::iter::repeat_n(color.into_storage(), n);
+        emu.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+    }
+
+    fn range(start: u16, end: u16) -> [u8; 4] {
+        let [s0, s1] = start.to_be_bytes();
+        let [e0, e1] = end.to_be_bytes();
+        [s0, s1, e0, e1]
+    }
+
+    #[test]
+    fn window_wraps_to_its_start() {
+        let mut emu = landscape();
+        // Five pixels into a 2x2 window: the fifth lands on the first.
+        fill(&mut emu, 10, 20, 11, 21, Rgb565::RED, 4);
+        let mut iter = [Rgb565::BLUE.into_storage()].into_iter();
+        emu.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+
+        assert_eq!(emu.pixel(10, 20), BLUE);
+        assert_eq!(emu.pixel(11, 20), RED);
+        assert_eq!(emu.pixel(10, 21), RED);
+        assert_eq!(emu.pixel(11, 21), RED);
+        assert_eq!(emu.pixel(12, 20), BLACK);
+        assert_eq!(emu.pixel(10, 22), BLACK);
+    }
+
+    #[test]
+    fn ramwrc_continues() {
+        let mut emu = landscape();
+        fill(&mut emu, 0, 0, 2, 0, Rgb565::RED, 1);
+        emu.send_commands(DataFormat::U8(&[0x3C])).unwrap();
+        let mut iter = [Rgb565::BLUE.into_storage()].into_iter();
+        emu.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+
+        assert_eq!(emu.pixel(0, 0), RED);
+        assert_eq!(emu.pixel(1, 0), BLUE);
+    }
+
+    #[test]
+    fn madctl_orientations() {
+        // Portrait, no exchange: column 0 / page 0 is the panel origin,
+        // which is (0, 0) of the landscape dump transposed.
+        let mut emu = Emulator::new();
+        send(&mut emu, 0x36, &[MADCTL_BGR]);
+        send(&mut emu, 0x3A, &[0x55]);
+        fill(&mut emu, 5, 7, 5, 7, Rgb565::RED, 1);
+        assert_eq!(emu.pixel(7, 5), RED);
+
+        // MX mirrors the column address.
+        send(&mut emu, 0x36, &[MADCTL_MV | MADCTL_MX | MADCTL_BGR]);
+        fill(&mut emu, 0, 0, 0, 0, Rgb565::BLUE, 1);
+        assert_eq!(emu.pixel(479, 0), BLUE);
+
+        // MY mirrors the page address.
+        send(&mut emu, 0x36, &[MADCTL_MV | MADCTL_MY | MADCTL_BGR]);
+        fill(&mut emu, 0, 0, 0, 0, Rgb565::BLUE, 1);
+        assert_eq!(emu.pixel(0, 319), BLUE);
+    }
+
+    #[test]
+    fn out_of_range_addresses_are_dropped() {
+        let mut emu = landscape();
+        fill(&mut emu, 479, 0, 480, 0, Rgb565::RED, 2);
+        assert_eq!(emu.pixel(479, 0), RED);
+        assert_eq!(emu.pixel(0, 1), BLACK);
+    }
+
+    #[test]
+    fn colmod_18bit() {
+        let mut emu = landscape();
+        send(&mut emu, 0x3A, &[0x66]);
+        send(&mut emu, 0x2A, &range(0, 1));
+        send(&mut emu, 0x2B, &range(0, 0));
+        send(&mut emu, 0x2C, &[0xFC, 0x00, 0x00, 0x00, 0x80, 0xFC]);
+        assert_eq!(emu.pixel(0, 0), RED);
+        assert_eq!(emu.pixel(1, 0), [0x00, 0x82, 0xFF]);
+    }
+
+    #[test]
+    fn inversion_and_bgr() {
+        let mut emu = landscape();
+        fill(&mut emu, 0, 0, 0, 0, Rgb565::RED, 1);
+        send(&mut emu, 0x21, &[]);
+        assert_eq!(emu.pixel(0, 0), [0x00, 0xFF, 0xFF]);
+        send(&mut emu, 0x20, &[]);
+        send(&mut emu, 0x36, &[MADCTL_MV]);
+        assert_eq!(emu.pixel(0, 0), BLUE);
+    }
+
+    #[test]
+    fn ppm_dump() {
+        let emu = landscape();
+        let mut ppm = Vec::new();
+        emu.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n480 320\n255\n"));
+        assert_eq!(ppm.len(), 15 + WIDTH * HEIGHT * 3);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut emu = landscape();
+        fill(&mut emu, 100, 100, 109, 109, Rgb565::RED, 100);
+        let mut png = Vec::new();
+        emu.write_png(&mut png).unwrap();
+        assert_eq!(read_png(png.as_slice()).unwrap(), emu.rgb888());
+    }
+
+    fn display() -> ILI9488<Emulator, NoopPin, NoopPin> {
+        let mut display = ILI9488::new(Emulator::new(), None, None, 480, 320);
+        display.init(&mut MockDelay::default()).unwrap();
+        display
+    }
+
+    #[test]
+    fn init_selects_landscape_rgb565() {
+        let mut display = display();
+        assert_eq!(display.di_mut().madctl(), 0x28);
+        assert_eq!(display.di_mut().colmod(), 0x55);
+    }
+
+    #[test]
+    fn golden_primitives() {
+        let mut display = display();
+        display.clear(Rgb565::new(0, 5, 10)).unwrap();
+
+        let stroke = PrimitiveStyleBuilder::new()
+            .stroke_color(Rgb565::CYAN)
+            .stroke_width(3)
+            .stroke_alignment(StrokeAlignment::Inside)
+            .build();
+        Rectangle::new(Point::new(0, 0), Size::new(480, 320))
+            .into_styled(stroke)
+            .draw(&mut display)
+            .unwrap();
+        Triangle::new(
+            Point::new(40, 120),
+            Point::new(120, 120),
+            Point::new(80, 50),
+        )
+        .into_styled(PrimitiveStyle::with_fill(Rgb565::YELLOW))
+        .draw(&mut display)
+        .unwrap();
+        Rectangle::new(Point::new(160, 50), Size::new(100, 70))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::RED))
+            .draw(&mut display)
+            .unwrap();
+        Circle::new(Point::new(300, 50), 70)
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::GREEN, 5))
+            .draw(&mut display)
+            .unwrap();
+        Line::new(Point::new(20, 300), Point::new(460, 160))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+            .draw(&mut display)
+            .unwrap();
+        Text::new(
+            "Hello ILI9488",
+            Point::new(40, 220),
+            MonoTextStyle::new(&FONT_10X20, Rgb565::MAGENTA),
+        )
+        .draw(&mut display)
+        .unwrap();
+
+        display.di_mut().assert_golden("primitives");
+    }
+}
@@ -0,0 +1,259 @@
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::digital::OutputPin;
+
+use crate::BusPixelFormat;
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// Bit-banged 8080 bus on plain GPIOs, for boards where all PIO state
+/// machines are taken.
+///
+/// `N` is the bus width and must be 8 or 16. The data pins need to share one
+/// type, with rp2040-hal pins that means converting them with
+/// `into_dyn_pin()` first.
+pub struct GpioParallelBus<DC, WR, P, const N: usize> {
+    dc: DC,
+    wr: WR,
+    data: [P; N],
+    last: Option<u16>,
+}
+
+impl<DC, WR, P, const N: usize> GpioParallelBus<DC, WR, P, N>
+where
+    DC: OutputPin,
+    WR: OutputPin,
+    P: OutputPin,
+{
+    pub fn new(dc: DC, mut wr: WR, data: [P; N]) -> Result<Self> {
+        const { assert!(N == 8 || N == 16, "bus width must be 8 or 16") };
+
+        wr.set_high().map_err(|_| DisplayError::BusWriteError)?;
+        Ok(Self {
+            dc,
+            wr,
+            data,
+            last: None,
+        })
+    }
+
+    pub fn free(self) -> (DC, WR, [P; N]) {
+        (self.dc, self.wr, self.data)
+    }
+
+    /// Puts `value` on the data lines and latches it with a WR strobe.
+    /// Only the lines that changed since the last write are touched.
+    fn write_value(&mut self, value: u16) -> Result {
+        let changed = match self.last {
+            Some(last) => last ^ value,
+            None => u16::MAX,
+        };
+        for (bit, pin) in self.data.iter_mut().enumerate() {
+            let mask = 1 << bit;
+            if changed & mask == 0 {
+                continue;
+            }
+            if value & mask != 0 {
+                pin.set_high()
+            } else {
+                pin.set_low()
+            }
+            .map_err(|_| DisplayError::BusWriteError)?;
+        }
+        self.last = Some(value);
+
+        self.wr.set_low().map_err(|_| DisplayError::BusWriteError)?;
+        self.wr
+            .set_high()
+            .map_err(|_| DisplayError::BusWriteError)?;
+        Ok(())
+    }
+
+    fn write_iter(&mut self, iter: impl Iterator<Item = u16>) -> Result {
+        for value in iter {
+            self.write_value(value)?;
+        }
+        Ok(())
+    }
+
+    fn write_pairs(&mut self, iter: impl Iterator<Item = [u8; 2]>) -> Result {
+        use core::iter::once;
+        self.write_iter(
+            iter.flat_map(|[first, second]| once(first).chain(once(second)))
+                .map(u16::from),
+        )
+    }
+
+    pub fn write_data(&mut self, data: DataFormat<'_>) -> Result {
+        if N == 16 {
+            // Same as `Pio16BitBus`: one value per strobe.
+            return match data {
+                DataFormat::U8(slice) => self.write_iter(slice.iter().map(|&v| v as u16)),
+                DataFormat::U8Iter(iter) => self.write_iter(iter.map(u16::from)),
+                DataFormat::U16(slice) => self.write_iter(slice.iter().copied()),
+                DataFormat::U16BE(slice) => self.write_iter(slice.iter().copied()),
+                DataFormat::U16LE(slice) => self.write_iter(slice.iter().copied()),
+                DataFormat::U16BEIter(iter) => self.write_iter(iter),
+                DataFormat::U16LEIter(iter) => self.write_iter(iter),
+                _ => Err(DisplayError::DataFormatNotImplemented),
+            };
+        }
+
+        match data {
+            DataFormat::U8(slice) => self.write_iter(slice.iter().map(|&v| v as u16)),
+            DataFormat::U8Iter(iter) => self.write_iter(iter.map(u16::from)),
+            DataFormat::U16(slice) => self.write_pairs(slice.iter().map(|v| v.to_ne_bytes())),
+            DataFormat::U16BE(slice) => self.write_pairs(slice.iter().map(|v| v.to_be_bytes())),
+            DataFormat::U16LE(slice) => self.write_pairs(slice.iter().map(|v| v.to_le_bytes())),
+            DataFormat::U16BEIter(iter) => self.write_pairs(iter.map(u16::to_be_bytes)),
+            DataFormat::U16LEIter(iter) => self.write_pairs(iter.map(u16::to_le_bytes)),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+}
+
+impl<DC, WR, P, const N: usize> BusPixelFormat for GpioParallelBus<DC, WR, P, N>
+where
+    DC: OutputPin,
+    WR: OutputPin,
+    P: OutputPin,
+{
+    const THREE_BYTE_PIXELS: bool = N == 8;
+}
+
+impl<DC, WR, P, const N: usize> WriteOnlyDataCommand for GpioParallelBus<DC, WR, P, N>
+where
+    DC: OutputPin,
+    WR: OutputPin,
+    P: OutputPin,
+{
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.write_data(cmd)?;
+        Ok(())
+    }
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.write_data(buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Pin levels of the whole bus, shared by all mock pins.
+    #[derive(Default)]
+    struct Lines {
+        dc: bool,
+        data: u16,
+        /// `(dc, data)` latched on every rising WR edge.
+        latched: Vec<(bool, u16)>,
+        pin_writes: usize,
+    }
+
+    enum Role {
+        Dc,
+        Wr,
+        Data(u8),
+    }
+
+    struct MockPin {
+        role: Role,
+        lines: Rc<RefCell<Lines>>,
+    }
+
+    impl embedded_hal::digital::ErrorType for MockPin {
+        type Error = Infallible;
+    }
+
+    impl MockPin {
+        fn set(&mut self, high: bool) -> core::result::Result<(), Infallible> {
+            let mut lines = self.lines.borrow_mut();
+            match self.role {
+                Role::Dc => lines.dc = high,
+                Role::Wr => {
+                    if high {
+                        let latched = (lines.dc, lines.data);
+                        lines.latched.push(latched);
+                    }
+                }
+                Role::Data(bit) => {
+                    lines.pin_writes += 1;
+                    lines.data = lines.data & !(1 << bit) | (high as u16) << bit;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl OutputPin for MockPin {
+        fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+            self.set(false)
+        }
+
+        fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+            self.set(true)
+        }
+    }
+
+    fn bus<const N: usize>() -> (
+        GpioParallelBus<MockPin, MockPin, MockPin, N>,
+        Rc<RefCell<Lines>>,
+    ) {
+        let lines = Rc::new(RefCell::new(Lines::default()));
+        let pin = |role| MockPin {
+            role,
+            lines: lines.clone(),
+        };
+        let data = core::array::from_fn(|bit| pin(Role::Data(bit as u8)));
+        let bus = GpioParallelBus::new(pin(Role::Dc), pin(Role::Wr), data).unwrap();
+        // Drop the strobe from releasing WR in `new`.
+        lines.borrow_mut().latched.clear();
+        (bus, lines)
+    }
+
+    #[test]
+    fn command_then_data_8bit() {
+        let (mut bus, lines) = bus::<8>();
+        bus.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        let mut iter = [0xF81Fu16].into_iter();
+        bus.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+
+        assert_eq!(
+            lines.borrow().latched,
+            [(false, 0x2C), (true, 0xF8), (true, 0x1F)]
+        );
+    }
+
+    #[test]
+    fn little_endian_8bit() {
+        let (mut bus, lines) = bus::<8>();
+        bus.send_data(DataFormat::U16LE(&mut [0x1234])).unwrap();
+        assert_eq!(lines.borrow().latched, [(true, 0x34), (true, 0x12)]);
+    }
+
+    #[test]
+    fn whole_words_16bit() {
+        let (mut bus, lines) = bus::<16>();
+        bus.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        bus.send_data(DataFormat::U16(&[0xF81F, 0x07E0])).unwrap();
+
+        assert_eq!(
+            lines.borrow().latched,
+            [(false, 0x2C), (true, 0xF81F), (true, 0x07E0)]
+        );
+    }
+
+    #[test]
+    fn only_changed_lines_are_written() {
+        let (mut bus, lines) = bus::<8>();
+        bus.send_data(DataFormat::U8(&[0x00, 0x00, 0x01])).unwrap();
+        // All eight lines for the first value, none for the repeat, then one.
+        assert_eq!(lines.borrow().pin_writes, 9);
+        assert_eq!(lines.borrow().latched.len(), 3);
+    }
+}
@@ -1,39 +1,51 @@
-use crate::ILI9488;
+use crate::{ColorFormat, RepeatDataCommand, ILI9488};
 use core::result::Result;
-use defmt::info;
 use display_interface::{DisplayError, WriteOnlyDataCommand};
-use embedded_graphics::prelude::IntoStorage;
 use embedded_graphics_core::{
     draw_target::DrawTarget,
-    geometry::{Dimensions, OriginDimensions, Size},
-    pixelcolor::Rgb565,
-    primitives::Rectangle,
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    primitives::{PointsIter, Rectangle},
     Pixel,
 };
 use embedded_hal::digital::OutputPin;
 
 // type Result<T = ()> = core::result::Result<T, DisplayError>;
-impl<DI, RST, BL> DrawTarget for ILI9488<DI, RST, BL>
+
+/// Longest run of pixels `draw_iter` collects before writing it out.
+const MAX_RUN: usize = 64;
+
+impl<DI, RST, BL, C, TE> DrawTarget for ILI9488<DI, RST, BL, C, TE>
 where
     DI: WriteOnlyDataCommand,
     RST: OutputPin,
     BL: OutputPin,
+    C: ColorFormat,
 {
-    type Color = Rgb565;
+    type Color = C;
     type Error = DisplayError;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        for pixel in pixels {
-            let x = pixel.0.x as u16;
-            let y = pixel.0.y as u16;
-
-            self.set_addr_win(x, y, x, y)?;
-            self.write_pixels([pixel.1])?;
+        // Horizontally adjacent pixels are collected into runs that go out
+        // as one window each.
+        let screen = self.bounding_box();
+        let mut run = heapless::Vec::<C, MAX_RUN>::new();
+        let mut start = Point::zero();
+        for Pixel(point, color) in pixels {
+            if !screen.contains(point) {
+                continue;
+            }
+            if run.is_full() || point != start + Point::new(run.len() as i32, 0) {
+                self.write_run(start, &run)?;
+                run.clear();
+                start = point;
+            }
+            // Not full, checked above.
+            let _ = run.push(color);
         }
-        Ok(())
+        self.write_run(start, &run)
     }
 
     fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
@@ -45,50 +57,561 @@ where
             // No intersection -> nothing to draw
             return Ok(());
         };
-        let xs = area.top_left.x as u16;
-        let ys = area.top_left.y as u16;
+        let xs = intersection.top_left.x as u16;
+        let ys = intersection.top_left.y as u16;
         let xe = bottom_right.x as u16;
         let ye = bottom_right.y as u16;
 
-        // let count = intersection.size.width * intersection.size.height;
-
+        // Iterators may run past the end of the area, never send more than
+        // the window holds.
+        let count = (intersection.size.width * intersection.size.height) as usize;
         let colors = colors.into_iter();
+        self.set_addr_win(xs, ys, xe, ye)?;
         if &intersection == area {
             // Draw the original iterator if no edge overlaps the framebuffer
-            self.set_addr_win(xs, ys, xe, ye)?;
-            self.write_pixels(colors)?;
+            self.write_pixels(colors.take(count))?;
         } else {
-            info!("overlap not supports yet!");
+            // The colours cover all of `area` row by row, drop the ones that
+            // fall off screen.
+            let visible = area
+                .points()
+                .zip(colors)
+                .filter(|(point, _)| intersection.contains(*point))
+                .map(|(_, color)| color)
+                .take(count);
+            self.write_pixels(visible)?;
         }
         Ok(())
     }
 
     fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
-        let Some(bottom_right) = area.bottom_right() else {
+        let count = self.start_clipped_write(area)?;
+        if count > 0 {
+            self.write_pixels(core::iter::repeat_n(color, count as usize))?;
+        }
+        Ok(())
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Starts a GRAM write to the on-screen part of `area`, returning its
+    /// pixel count. Nothing is sent if `area` is entirely off screen.
+    fn start_clipped_write(&mut self, area: &Rectangle) -> Result<u32, DisplayError> {
+        let intersection = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = intersection.bottom_right() else {
             // No intersection -> nothing to draw
-            return Ok(());
+            return Ok(0);
         };
-        let xs = area.top_left.x as u16;
-        let ys = area.top_left.y as u16;
+        let xs = intersection.top_left.x as u16;
+        let ys = intersection.top_left.y as u16;
         let xe = bottom_right.x as u16;
         let ye = bottom_right.y as u16;
 
-        let count = area.size.width * area.size.height;
-        let colors = core::iter::repeat(color).take(count.try_into().unwrap());
-
         self.set_addr_win(xs, ys, xe, ye)?;
-        self.write_pixels(colors)?;
+        Ok(intersection.size.width * intersection.size.height)
+    }
+
+    /// Writes `run` to the row starting at `start`.
+    fn write_run(&mut self, start: Point, run: &[C]) -> Result<(), DisplayError> {
+        if run.is_empty() {
+            return Ok(());
+        }
+        let x = start.x as u16;
+        let y = start.y as u16;
+        self.set_addr_win(x, y, x + run.len() as u16 - 1, y)?;
+        self.write_pixels(run.iter().copied())
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: RepeatDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Like [`DrawTarget::fill_solid`], but the bus repeats `color` by
+    /// itself instead of being fed every pixel.
+    pub fn fill_repeated(&mut self, area: &Rectangle, color: C) -> Result<(), DisplayError> {
+        let count = self.start_clipped_write(area)?;
+        if count > 0 {
+            C::send_repeated(&mut self.di, color, count)?;
+        }
         Ok(())
     }
+
+    /// Like [`ILI9488::clear`], but the bus repeats `color` by itself.
+    pub fn clear_repeated(&mut self, color: C) -> Result<(), DisplayError> {
+        self.fill_repeated(&self.bounding_box(), color)
+    }
 }
 
-impl<DI, RST, BL> OriginDimensions for ILI9488<DI, RST, BL>
+impl<DI, RST, BL, C, TE> OriginDimensions for ILI9488<DI, RST, BL, C, TE>
 where
     DI: WriteOnlyDataCommand,
     RST: OutputPin,
     BL: OutputPin,
+    C: ColorFormat,
 {
     fn size(&self) -> Size {
         Size::new(self.size_x as u32, self.size_y as u32)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockInterface, NoopPin};
+    use embedded_graphics::pixelcolor::{raw::RawU16, Rgb565};
+    use embedded_graphics::{
+        prelude::{Point, Primitive, RgbColor},
+        primitives::PrimitiveStyle,
+        Drawable,
+    };
+
+    fn display() -> ILI9488<MockInterface, NoopPin, NoopPin> {
+        ILI9488::new(MockInterface::new(), None, None, 480, 320)
+    }
+
+    /// A distinct colour for every pixel of an area (up to 65536 pixels),
+    /// in row-major order.
+    fn numbered(count: u32) -> impl Iterator<Item = Rgb565> {
+        (0..count).map(|i| RawU16::new(i as u16).into())
+    }
+
+    /// What `fill_contiguous` should send for `numbered` colours covering
+    /// `area`: the pixels inside `visible`, row by row.
+    fn visible_part(area: &Rectangle, visible: &Rectangle) -> Vec<Rgb565> {
+        area.points()
+            .zip(numbered(u32::MAX))
+            .filter(|(p, _)| visible.contains(*p))
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    /// A 20x10 area at `(x, y)`.
+    fn area(x: i32, y: i32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(20, 10))
+    }
+
+    /// Fills `area` with `fill_contiguous` and `fill_solid`, checking both
+    /// only touch the on-screen window `(xs, ys, xe, ye)`.
+    #[track_caller]
+    fn assert_clipped(area: Rectangle, (xs, ys, xe, ye): (u16, u16, u16, u16)) {
+        let visible = Rectangle::with_corners(
+            Point::new(xs.into(), ys.into()),
+            Point::new(xe.into(), ye.into()),
+        );
+        let count = area.size.width * area.size.height;
+
+        let mut contiguous = display();
+        contiguous.fill_contiguous(&area, numbered(count)).unwrap();
+        contiguous
+            .di
+            .expect()
+            .window(xs, ys, xe, ye)
+            .ramwr_pixels(&visible_part(&area, &visible))
+            .end();
+
+        let mut solid = display();
+        solid.fill_solid(&area, Rgb565::RED).unwrap();
+        let pixels = (visible.size.width * visible.size.height) as usize;
+        solid
+            .di
+            .expect()
+            .window(xs, ys, xe, ye)
+            .ramwr_solid(Rgb565::RED, pixels)
+            .end();
+    }
+
+    #[test]
+    fn draw_iter_one_window_per_pixel() {
+        let mut display = display();
+        let pixels = [
+            Pixel(Point::new(1, 2), Rgb565::RED),
+            Pixel(Point::new(479, 319), Rgb565::BLUE),
+        ];
+        display.draw_iter(pixels).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(1, 2, 1, 2)
+            .ramwr_pixels(&[Rgb565::RED])
+            .window(479, 319, 479, 319)
+            .ramwr_pixels(&[Rgb565::BLUE])
+            .end();
+    }
+
+    #[test]
+    fn draw_iter_merges_adjacent_pixels() {
+        let mut display = display();
+        let pixels = [
+            Pixel(Point::new(10, 5), Rgb565::RED),
+            Pixel(Point::new(11, 5), Rgb565::GREEN),
+            Pixel(Point::new(12, 5), Rgb565::BLUE),
+            // Next row, same columns.
+            Pixel(Point::new(10, 6), Rgb565::RED),
+            Pixel(Point::new(11, 6), Rgb565::RED),
+            // Gap, then going backwards.
+            Pixel(Point::new(13, 6), Rgb565::BLUE),
+            Pixel(Point::new(12, 6), Rgb565::GREEN),
+        ];
+        display.draw_iter(pixels).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(10, 5, 12, 5)
+            .ramwr_pixels(&[Rgb565::RED, Rgb565::GREEN, Rgb565::BLUE])
+            .caset(10, 11)
+            .paset(6, 6)
+            .ramwr_pixels(&[Rgb565::RED, Rgb565::RED])
+            .caset(13, 13)
+            .ramwr_pixels(&[Rgb565::BLUE])
+            .caset(12, 12)
+            .ramwr_pixels(&[Rgb565::GREEN])
+            .end();
+    }
+
+    #[test]
+    fn draw_iter_splits_long_runs() {
+        let mut display = display();
+        let pixels = (0..100).map(|x| Pixel(Point::new(x, 0), Rgb565::WHITE));
+        display.draw_iter(pixels).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(0, 0, 63, 0)
+            .ramwr_solid(Rgb565::WHITE, 64)
+            .caset(64, 99)
+            .ramwr_solid(Rgb565::WHITE, 36)
+            .end();
+    }
+
+    #[test]
+    fn draw_iter_does_not_merge_across_rows() {
+        let mut display = display();
+        let pixels = [
+            Pixel(Point::new(479, 0), Rgb565::RED),
+            Pixel(Point::new(0, 1), Rgb565::GREEN),
+        ];
+        display.draw_iter(pixels).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(479, 0, 479, 0)
+            .ramwr_pixels(&[Rgb565::RED])
+            .window(0, 1, 0, 1)
+            .ramwr_pixels(&[Rgb565::GREEN])
+            .end();
+    }
+
+    #[test]
+    fn unchanged_window_is_not_resent() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(10, 20), Size::new(3, 2));
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+        display.fill_solid(&area, Rgb565::RED).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(10, 20, 12, 21)
+            .ramwr_solid(Rgb565::BLUE, 6)
+            .ramwr_solid(Rgb565::RED, 6)
+            .end();
+    }
+
+    #[test]
+    fn window_is_resent_after_manual_writes() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(10, 20), Size::new(3, 2));
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+        display.write_reg(&[0x2A, 0, 0, 0, 0]).unwrap();
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+        display.write_reg(&[0x01]).unwrap();
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(10, 20, 12, 21)
+            .ramwr(6)
+            .caset(0, 0)
+            .caset(10, 12)
+            .ramwr(6)
+            .command(0x01, &[])
+            .window(10, 20, 12, 21)
+            .ramwr(6)
+            .end();
+    }
+
+    /// The `draw_iter` of old, one full window per pixel.
+    struct PerPixel<'a>(&'a mut ILI9488<MockInterface, NoopPin, NoopPin>);
+
+    impl DrawTarget for PerPixel<'_> {
+        type Color = Rgb565;
+        type Error = DisplayError;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Rgb565>>,
+        {
+            for pixel in pixels {
+                (self.0.columns, self.0.pages) = (None, None);
+                self.0.draw_iter([pixel])?;
+            }
+            Ok(())
+        }
+    }
+
+    impl OriginDimensions for PerPixel<'_> {
+        fn size(&self) -> Size {
+            self.0.size()
+        }
+    }
+
+    /// Bus bytes per glyph of text without background, which is drawn
+    /// pixel by pixel.
+    #[test]
+    fn bytes_per_glyph() {
+        use embedded_graphics::{
+            mono_font::{ascii::FONT_10X20, MonoTextStyle},
+            text::{Baseline, Text},
+        };
+
+        const TEXT: &str = "The quick brown fox jumps over the lazy dog";
+        let glyphs = TEXT.len();
+        let text = Text::with_baseline(
+            TEXT,
+            Point::new(10, 10),
+            MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE),
+            Baseline::Top,
+        );
+
+        let mut per_pixel = display();
+        text.draw(&mut PerPixel(&mut per_pixel)).unwrap();
+        let before = per_pixel.di.bytes() / glyphs;
+
+        let mut batched = display();
+        text.draw(&mut batched).unwrap();
+        let after = batched.di.bytes() / glyphs;
+
+        assert_eq!(before, 379);
+        assert_eq!(after, 160);
+    }
+
+    #[test]
+    fn fill_contiguous_streams_area() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(100, 50), Size::new(2, 2));
+        let colors = [Rgb565::RED, Rgb565::GREEN, Rgb565::BLUE, Rgb565::WHITE];
+        display.fill_contiguous(&area, colors).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(100, 50, 101, 51)
+            .ramwr_pixels(&colors)
+            .end();
+    }
+
+    #[test]
+    fn fill_solid_streams_area() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(10, 20), Size::new(3, 2));
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+
+        display
+            .di
+            .expect()
+            .window(10, 20, 12, 21)
+            .ramwr_solid(Rgb565::BLUE, 6)
+            .end();
+    }
+
+    #[test]
+    fn empty_areas_send_nothing() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(10, 20), Size::zero());
+        display.fill_solid(&area, Rgb565::BLUE).unwrap();
+        display.fill_contiguous(&area, [Rgb565::RED]).unwrap();
+        display.di.expect().end();
+    }
+
+    #[test]
+    fn clipped_at_each_edge() {
+        // Left, right, top, bottom.
+        assert_clipped(area(-5, 100), (0, 100, 14, 109));
+        assert_clipped(area(470, 100), (470, 100, 479, 109));
+        assert_clipped(area(100, -3), (100, 0, 119, 6));
+        assert_clipped(area(100, 315), (100, 315, 119, 319));
+    }
+
+    #[test]
+    fn clipped_at_each_corner() {
+        assert_clipped(area(-5, -3), (0, 0, 14, 6));
+        assert_clipped(area(470, -3), (470, 0, 479, 6));
+        assert_clipped(area(-5, 315), (0, 315, 14, 319));
+        assert_clipped(area(470, 315), (470, 315, 479, 319));
+    }
+
+    #[test]
+    fn clipped_on_all_sides() {
+        let area = Rectangle::new(Point::new(-2, -1), Size::new(484, 322));
+        assert_clipped(area, (0, 0, 479, 319));
+    }
+
+    #[test]
+    fn single_visible_pixel() {
+        let area = Rectangle::new(Point::new(-9, -9), Size::new(10, 10));
+        assert_clipped(area, (0, 0, 0, 0));
+        let area = Rectangle::new(Point::new(479, 319), Size::new(10, 10));
+        assert_clipped(area, (479, 319, 479, 319));
+    }
+
+    #[test]
+    fn fill_repeated_is_clipped() {
+        let mut display = display();
+        display.fill_repeated(&area(470, -3), Rgb565::RED).unwrap();
+        display.fill_repeated(&area(-20, -10), Rgb565::RED).unwrap();
+        display
+            .di
+            .expect()
+            .window(470, 0, 479, 6)
+            .ramwr_solid(Rgb565::RED, 10 * 7)
+            .end();
+        assert_eq!(display.di.repeats(), 1);
+    }
+
+    #[test]
+    fn off_screen_areas_send_nothing() {
+        let mut display = display();
+        for (x, y) in [
+            (-20, 100),
+            (480, 100),
+            (100, -10),
+            (100, 320),
+            (-20, -10),
+            (480, 320),
+        ] {
+            display.fill_solid(&area(x, y), Rgb565::RED).unwrap();
+            display.fill_contiguous(&area(x, y), numbered(200)).unwrap();
+        }
+        display.di.expect().end();
+    }
+
+    #[test]
+    fn long_color_iterator() {
+        let mut display = display();
+        let area = Rectangle::new(Point::new(10, 10), Size::new(2, 1));
+        display.fill_contiguous(&area, numbered(5)).unwrap();
+        display
+            .di
+            .expect()
+            .window(10, 10, 11, 10)
+            .ramwr_pixels(&numbered(2).collect::<Vec<_>>())
+            .end();
+    }
+
+    #[test]
+    fn short_color_iterator() {
+        // Fewer colours than the area needs: whatever there is gets sent.
+        let mut display = display();
+        let area = Rectangle::new(Point::new(-1, 0), Size::new(3, 2));
+        display.fill_contiguous(&area, numbered(4)).unwrap();
+        let sent: Vec<Rgb565> = [1, 2].into_iter().map(|i| RawU16::new(i).into()).collect();
+        display
+            .di
+            .expect()
+            .window(0, 0, 1, 1)
+            .ramwr_pixels(&sent)
+            .end();
+    }
+
+    #[test]
+    fn draw_iter_skips_off_screen_pixels() {
+        let mut display = display();
+        let pixels = [
+            Pixel(Point::new(-1, 0), Rgb565::RED),
+            Pixel(Point::new(0, -1), Rgb565::RED),
+            Pixel(Point::new(480, 0), Rgb565::RED),
+            Pixel(Point::new(0, 320), Rgb565::RED),
+            Pixel(Point::new(0, 0), Rgb565::GREEN),
+        ];
+        display.draw_iter(pixels).unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 0, 0)
+            .ramwr_pixels(&[Rgb565::GREEN])
+            .end();
+    }
+
+    #[test]
+    fn text_sliding_in_from_the_left() {
+        use embedded_graphics::{
+            mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
+            text::{Baseline, Text},
+        };
+
+        let style = MonoTextStyleBuilder::new()
+            .font(&FONT_10X20)
+            .text_color(Rgb565::WHITE)
+            .background_color(Rgb565::BLACK)
+            .build();
+        // Glyphs with a background are drawn as whole cells, 2.5 of the 4
+        // cells are visible.
+        let mut display = display();
+        Text::with_baseline("ABCD", Point::new(-15, 0), style, Baseline::Top)
+            .draw(&mut display)
+            .unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 4, 19)
+            .ramwr(5 * 20)
+            .caset(5, 14)
+            .ramwr(10 * 20)
+            .caset(15, 24)
+            .ramwr(10 * 20)
+            .end();
+    }
+
+    #[test]
+    fn styled_rectangle() {
+        let mut display = display();
+        Rectangle::new(Point::new(0, 300), Size::new(480, 20))
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+            .draw(&mut display)
+            .unwrap();
+
+        display
+            .di
+            .expect()
+            .window(0, 300, 479, 319)
+            .ramwr(480 * 20)
+            .end();
+    }
+
+    #[test]
+    fn clear_is_one_full_screen_fill() {
+        let mut display = display();
+        DrawTarget::clear(&mut display, Rgb565::BLACK).unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 479, 319)
+            .ramwr_solid(Rgb565::BLACK, 480 * 320)
+            .end();
+    }
+}
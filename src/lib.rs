@@ -1,20 +1,275 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
 
-use cortex_m::delay::Delay;
 // use defmt::info;
+use core::marker::PhantomData;
 use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
-use embedded_graphics::{pixelcolor::Rgb565, prelude::IntoStorage};
-use embedded_hal::digital::OutputPin;
-use rp2040_hal::pio::{Tx, ValidStateMachine};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::Dimensions, primitives::Rectangle};
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+use fugit::HertzU32;
+use rp2040_hal::pio::{
+    Buffers, PIOBuilder, PIOExt, PinDir, Running, ShiftDirection, StateMachine, StateMachineIndex,
+    Tx, UninitStateMachine, ValidStateMachine, PIO,
+};
 
+mod backlight;
+mod builder;
+mod color;
+#[cfg(test)]
+mod emulator;
+mod gpio;
 mod graphics;
+#[cfg(test)]
+mod mock;
+mod orientation;
 pub mod overclock;
+mod pio_dma;
+mod pio_read;
+mod power;
+mod scroll;
+mod spi;
+mod tearing;
+mod timing;
+pub mod touch;
+
+use builder::InitRegisters;
+pub use backlight::{perceptual_duty, PwmBacklight};
+pub use builder::{
+    EntryMode, FrameRate, GammaCurve, ILI9488Builder, Inversion, PowerControl, RawCommand, Vcom,
+};
+pub use color::ColorFormat;
+pub use gpio::GpioParallelBus;
+pub use orientation::Orientation;
+pub use pio_dma::PioDmaBus;
+pub use pio_read::PioReadBus;
+pub use power::{PowerError, PowerState};
+pub use scroll::ScrollArea;
+pub use spi::SpiInterface;
+pub use tearing::{TearingEffect, VsyncError};
+pub use timing::{
+    BusTiming, SetupError, TimingError, ILI9488_T_WC_NS, ILI9488_T_WRH_NS, ILI9488_T_WRL_NS,
+};
 
 type Result<T = ()> = core::result::Result<T, DisplayError>;
 
-pub struct Pio8BitBus<SM: ValidStateMachine, DC> {
-    tx: Tx<SM>,
+/// Read counterpart of [`WriteOnlyDataCommand`] for buses wired with RD.
+pub trait ReadDataCommand: WriteOnlyDataCommand {
+    /// Reads `buf.len()` bytes in data mode, following a command.
+    fn read_data(&mut self, buf: &mut [u8]) -> Result;
+}
+
+/// Read buses on the low eight data lines, where GRAM reads come back as
+/// three bytes per pixel. A 16-bit bus gets pixels on all sixteen lines.
+pub trait Read8BitDataCommand: ReadDataCommand {}
+
+/// Buses that can repeat a pixel on their own, so the CPU doesn't have to
+/// feed every byte of a solid fill.
+pub trait RepeatDataCommand: WriteOnlyDataCommand {
+    /// Sends `pixel` `count` times in data mode, following a command.
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result;
+}
+
+/// The pixel format a bus needs the panel to use, which `init` programs
+/// over the one selected on the driver.
+pub trait BusPixelFormat: WriteOnlyDataCommand {
+    /// `None` if the bus takes any format.
+    const PIXEL_FORMAT: Option<PixelFormat> = None;
+    /// Whether the bus can send the 18-bit format, three bytes per pixel.
+    /// A 16-bit bus would put every byte on a bus cycle of its own.
+    const THREE_BYTE_PIXELS: bool = true;
+}
+
+/// The TX FIFO of a PIO state machine.
+///
+/// The buses only need to push words and to know when the state machine has
+/// run dry, which also lets them run against a recording FIFO in host tests.
+pub trait PioTx {
+    /// Pushes a word, returns `false` if the FIFO is full.
+    fn write(&mut self, value: u32) -> bool;
+    fn is_empty(&self) -> bool;
+    /// Sticky flag, set once the state machine stalled on an empty FIFO.
+    fn has_stalled(&self) -> bool;
+    fn clear_stalled_flag(&mut self);
+}
+
+impl<SM: ValidStateMachine> PioTx for Tx<SM> {
+    fn write(&mut self, value: u32) -> bool {
+        Tx::write(self, value)
+    }
+
+    fn is_empty(&self) -> bool {
+        Tx::is_empty(self)
+    }
+
+    fn has_stalled(&self) -> bool {
+        Tx::has_stalled(self)
+    }
+
+    fn clear_stalled_flag(&mut self) {
+        Tx::clear_stalled_flag(self)
+    }
+}
+
+/// Waits until the state machine behind `tx` has run dry. The FIFO running
+/// empty isn't enough, the last word may still be in the OSR.
+fn pio_flush(tx: &mut impl PioTx) {
+    tx.clear_stalled_flag();
+    while !tx.has_stalled() {}
+}
+
+/// Like [`pio_flush`], polling every microsecond and giving up after
+/// `timeout_us` with a `BusWriteError`.
+fn pio_flush_timeout(
+    tx: &mut impl PioTx,
+    delay_source: &mut impl DelayNs,
+    timeout_us: u32,
+) -> Result {
+    tx.clear_stalled_flag();
+    let mut left = timeout_us;
+    while !tx.has_stalled() {
+        if left == 0 {
+            return Err(DisplayError::BusWriteError);
+        }
+        left -= 1;
+        delay_source.delay_us(1);
+    }
+    Ok(())
+}
+
+/// PIO program driving [`Pio8BitBus`], with the WR strobe shaped by
+/// `timing`.
+///
+/// Every transfer starts with a header word holding the byte count minus
+/// one, followed by the bytes packed four per word, least significant first.
+/// The state machine must use right shift, autopull and a pull threshold
+/// of 32. The tail of the last word is dropped, so a transfer whose length
+/// is a multiple of four carries an extra padding word.
+///
+/// With the top bit of the header set, the low 31 bits are a repeat count
+/// minus one instead, and the two low bytes of the next word are sent that
+/// many times over. The repeat loop moves them to the pins from Y and ISR
+/// and leaves the OSR alone, as autopull has already refilled it with the
+/// next header by then.
+pub fn pio8_program(timing: &BusTiming) -> pio::Program<{ pio::RP2040_MAX_PROGRAM_SIZE }> {
+    use pio::{JmpCondition, MovDestination, MovOperation, MovSource, OutDestination};
+
+    let mut a = pio::Assembler::new_with_side_set(pio::SideSet::new(false, 1, false));
+    let mut wrap_target = a.label();
+    let mut wrap_source = a.label();
+    let mut pair = a.label();
+    let mut byte = a.label();
+
+    a.bind(&mut wrap_target);
+    a.out_with_side_set(OutDestination::X, 31, 1); // byte or repeat count - 1
+    a.out_with_side_set(OutDestination::Y, 1, 1); // repeat flag
+    a.jmp_with_side_set(JmpCondition::YIsZero, &mut byte, 1);
+    a.out_with_side_set(OutDestination::Y, 8, 1); // first byte of the pair
+    a.out_with_side_set(OutDestination::ISR, 24, 1); // second byte
+    a.bind(&mut pair);
+    a.mov_with_delay_and_side_set(
+        MovDestination::PINS,
+        MovOperation::None,
+        MovSource::Y,
+        timing.wr_low - 1,
+        0,
+    );
+    a.nop_with_delay_and_side_set(timing.wr_high - 1, 1);
+    a.mov_with_delay_and_side_set(
+        MovDestination::PINS,
+        MovOperation::None,
+        MovSource::ISR,
+        timing.wr_low - 1,
+        0,
+    );
+    a.jmp_with_delay_and_side_set(JmpCondition::XDecNonZero, &mut pair, timing.wr_high - 1, 1);
+    a.jmp_with_side_set(JmpCondition::Always, &mut wrap_target, 1);
+    a.bind(&mut byte);
+    a.out_with_delay_and_side_set(OutDestination::PINS, 8, timing.wr_low - 1, 0);
+    a.jmp_with_delay_and_side_set(JmpCondition::XDecNonZero, &mut byte, timing.wr_high - 1, 1);
+    a.out_with_side_set(OutDestination::NULL, 32, 1); // drop the rest of the last word
+    a.bind(&mut wrap_source);
+    a.assemble_with_wrap(wrap_source, wrap_target)
+}
+
+/// PIO program driving [`Pio16BitBus`] and [`PioDmaBus`], one value per WR
+/// strobe. The state machine must use right shift, autopull and a pull
+/// threshold of 16, so only the low half of every FIFO word is used.
+///
+/// Every transfer starts with a header word holding the value count minus
+/// one, up to 32768 values, followed by one word per value. With bit 15 of
+/// the header set, the next word is sent count times over instead. The
+/// repeat loop moves the value from Y to the pins and leaves the OSR alone,
+/// as autopull has already refilled it with the next header by then.
+pub fn pio16_program(timing: &BusTiming) -> pio::Program<{ pio::RP2040_MAX_PROGRAM_SIZE }> {
+    use pio::{JmpCondition, MovDestination, MovOperation, MovSource, OutDestination};
+
+    let mut a = pio::Assembler::new_with_side_set(pio::SideSet::new(false, 1, false));
+    let mut wrap_target = a.label();
+    let mut wrap_source = a.label();
+    let mut fill = a.label();
+    let mut value = a.label();
+
+    a.bind(&mut wrap_target);
+    a.out_with_side_set(OutDestination::X, 15, 1); // value or repeat count - 1
+    a.out_with_side_set(OutDestination::Y, 1, 1); // repeat flag
+    a.jmp_with_side_set(JmpCondition::YIsZero, &mut value, 1);
+    a.out_with_side_set(OutDestination::Y, 16, 1); // the value to repeat
+    a.bind(&mut fill);
+    a.mov_with_delay_and_side_set(
+        MovDestination::PINS,
+        MovOperation::None,
+        MovSource::Y,
+        timing.wr_low - 1,
+        0,
+    );
+    a.jmp_with_delay_and_side_set(JmpCondition::XDecNonZero, &mut fill, timing.wr_high - 1, 1);
+    a.jmp_with_side_set(JmpCondition::Always, &mut wrap_target, 1);
+    a.bind(&mut value);
+    a.out_with_delay_and_side_set(OutDestination::PINS, 16, timing.wr_low - 1, 0);
+    a.jmp_with_delay_and_side_set(JmpCondition::XDecNonZero, &mut value, timing.wr_high - 1, 1);
+    a.bind(&mut wrap_source);
+    a.assemble_with_wrap(wrap_source, wrap_target)
+}
+
+/// State machine running the program of a bus made with `setup`.
+pub type BusStateMachine<P, SM> = StateMachine<(P, SM), Running>;
+
+type SetupResult<T> = core::result::Result<T, SetupError>;
+type BusParts<P, SM> = (BusStateMachine<P, SM>, Tx<(P, SM)>);
+
+/// Installs `program` and starts it on `sm`, with WR as side-set pin and
+/// `width` data pins from `d0`.
+fn start_bus_sm<P: PIOExt, SM: StateMachineIndex>(
+    pio: &mut PIO<P>,
+    sm: UninitStateMachine<(P, SM)>,
+    program: &pio::Program<{ pio::RP2040_MAX_PROGRAM_SIZE }>,
+    timing: &BusTiming,
+    wr: u8,
+    d0: u8,
+    width: u8,
+) -> SetupResult<BusParts<P, SM>> {
+    let installed = pio.install(program)?;
+    let (mut sm, _, tx) = PIOBuilder::from_installed_program(installed)
+        .side_set_pin_base(wr)
+        .out_pins(d0, width)
+        .buffers(Buffers::OnlyTx)
+        .clock_divisor_fixed_point(timing.divisor, 0)
+        .out_shift_direction(ShiftDirection::Right)
+        .autopull(true)
+        .pull_threshold(if width == 8 { 32 } else { 16 })
+        .build(sm);
+    let pins = core::iter::once(wr).chain(d0..d0 + width);
+    sm.set_pindirs(pins.map(|pin| (pin, PinDir::Output)));
+    Ok((sm.start(), tx))
+}
+
+/// Bytes buffered per transfer when the length isn't known up front.
+const PIO8_CHUNK: usize = 256;
+
+/// Header flag of a repeated transfer in [`pio8_program`].
+const PIO8_REPEAT: u32 = 1 << 31;
+
+pub struct Pio8BitBus<TX: PioTx, DC> {
+    tx: TX,
     dc: DC,
 }
 
@@ -25,19 +280,59 @@ pub struct Pio8BitBus<SM: ValidStateMachine, DC> {
 
 impl<TX, DC> Pio8BitBus<TX, DC>
 where
-    TX: ValidStateMachine,
+    TX: PioTx,
     DC: OutputPin,
 {
-    pub fn new(tx: Tx<TX>, dc: DC) -> Self {
+    pub fn new(tx: TX, dc: DC) -> Self {
         Self { tx, dc }
     }
 
-    fn write_iter(&mut self, iter: impl Iterator<Item = u8>) -> Result {
-        for value in iter {
-            self.tx.write(value as u32);
-            while !self.tx.is_empty() {}
+    /// Waits until the state machine has shifted out everything queued so
+    /// far, so DC can be toggled without cutting off the last bytes.
+    ///
+    /// Never returns if the state machine isn't running, see
+    /// [`flush_timeout`](Self::flush_timeout).
+    pub fn flush(&mut self) {
+        pio_flush(&mut self.tx);
+    }
+
+    /// Like [`flush`](Self::flush), giving up with a `BusWriteError` after
+    /// `timeout_us`.
+    pub fn flush_timeout(&mut self, delay_source: &mut impl DelayNs, timeout_us: u32) -> Result {
+        pio_flush_timeout(&mut self.tx, delay_source, timeout_us)
+    }
+
+    fn push(&mut self, word: u32) {
+        while !self.tx.write(word) {}
+    }
+
+    fn write_chunk(&mut self, bytes: &[u8]) {
+        self.push(bytes.len() as u32 - 1);
+        for quad in bytes.chunks(4) {
+            let mut word = [0; 4];
+            word[..quad.len()].copy_from_slice(quad);
+            self.push(u32::from_le_bytes(word));
+        }
+        if bytes.len().is_multiple_of(4) {
+            self.push(0);
+        }
+    }
+
+    fn write_iter(&mut self, mut iter: impl Iterator<Item = u8>) -> Result {
+        let mut buf = [0; PIO8_CHUNK];
+        loop {
+            let mut len = 0;
+            for (slot, value) in buf.iter_mut().zip(&mut iter) {
+                *slot = value;
+                len += 1;
+            }
+            if len > 0 {
+                self.write_chunk(&buf[..len]);
+            }
+            if len < PIO8_CHUNK {
+                return Ok(());
+            }
         }
-        Ok(())
     }
 
     fn write_pairs(&mut self, iter: impl Iterator<Item = [u8; 2]>) -> Result {
@@ -45,44 +340,350 @@ where
         self.write_iter(iter.flat_map(|[first, second]| once(first).chain(once(second))))
     }
 
+    /// Has the state machine send `pair` `count` times, split up if
+    /// `count` doesn't fit the header.
+    fn write_repeated(&mut self, pair: [u8; 2], mut count: u32) {
+        while count > 0 {
+            let n = count.min(PIO8_REPEAT);
+            self.push(PIO8_REPEAT | (n - 1));
+            self.push(u16::from_le_bytes(pair) as u32);
+            count -= n;
+        }
+    }
+
     pub fn write_data(&mut self, data: DataFormat<'_>) -> Result {
         match data {
-            DataFormat::U8(slice) => self.write_iter(slice.iter().copied()),
-            DataFormat::U16LEIter(iter) => self.write_pairs(iter.map(u16::to_be_bytes)),
+            DataFormat::U8(slice) => {
+                for chunk in slice.chunks(PIO8_CHUNK) {
+                    self.write_chunk(chunk);
+                }
+                Ok(())
+            }
+            DataFormat::U8Iter(iter) => self.write_iter(iter),
+            DataFormat::U16(slice) => self.write_pairs(slice.iter().map(|v| v.to_ne_bytes())),
+            DataFormat::U16BE(slice) => self.write_pairs(slice.iter().map(|v| v.to_be_bytes())),
+            DataFormat::U16LE(slice) => self.write_pairs(slice.iter().map(|v| v.to_le_bytes())),
+            DataFormat::U16BEIter(iter) => self.write_pairs(iter.map(u16::to_be_bytes)),
+            DataFormat::U16LEIter(iter) => self.write_pairs(iter.map(u16::to_le_bytes)),
             _ => Err(DisplayError::DataFormatNotImplemented),
         }
     }
 }
 
+impl<P, SM, DC> Pio8BitBus<Tx<(P, SM)>, DC>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+    DC: OutputPin,
+{
+    /// Installs [`pio8_program`] on `sm` with the finest timing that keeps
+    /// the write cycle at `sys_clk` above `min_write_cycle_ns`.
+    ///
+    /// `wr` and the eight data pins from `d0` must already be switched to
+    /// the PIO function. The running state machine is handed back for
+    /// [`PioReadBus`].
+    pub fn setup(
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        sys_clk: HertzU32,
+        min_write_cycle_ns: u32,
+        wr: u8,
+        d0: u8,
+        dc: DC,
+    ) -> SetupResult<(Self, BusStateMachine<P, SM>)> {
+        let timing = BusTiming::new(sys_clk, min_write_cycle_ns)?;
+        let program = pio8_program(&timing);
+        let (sm, tx) = start_bus_sm(pio, sm, &program, &timing, wr, d0, 8)?;
+        Ok((Self::new(tx, dc), sm))
+    }
+}
+
 impl<TX, DC> WriteOnlyDataCommand for Pio8BitBus<TX, DC>
 where
-    TX: ValidStateMachine,
+    TX: PioTx,
     DC: OutputPin,
 {
     fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        self.flush();
         self.dc.set_low().map_err(|_| DisplayError::DCError)?;
         self.write_data(cmd)?;
         Ok(())
     }
     fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.flush();
         self.dc.set_high().map_err(|_| DisplayError::DCError)?;
         self.write_data(buf)?;
         Ok(())
     }
 }
 
-pub struct ILI9488<DI, RST, BL>
+impl<TX: PioTx, DC: OutputPin> BusPixelFormat for Pio8BitBus<TX, DC> {}
+
+/// Two-byte pixels are repeated by the state machine, anything else is
+/// streamed from the CPU.
+impl<TX, DC> RepeatDataCommand for Pio8BitBus<TX, DC>
+where
+    TX: PioTx,
+    DC: OutputPin,
+{
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result {
+        self.flush();
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        let pair = match pixel {
+            DataFormat::U8(&[first, second]) => [first, second],
+            DataFormat::U16(&[value]) => value.to_ne_bytes(),
+            DataFormat::U16BE(&mut [value]) => value.to_be_bytes(),
+            DataFormat::U16LE(&mut [value]) => value.to_le_bytes(),
+            DataFormat::U8(bytes) => {
+                let len = bytes.len() * count as usize;
+                return self.write_iter(bytes.iter().copied().cycle().take(len));
+            }
+            _ => return Err(DisplayError::DataFormatNotImplemented),
+        };
+        self.write_repeated(pair, count);
+        Ok(())
+    }
+}
+
+/// Header flag of a repeated transfer in [`pio16_program`].
+const PIO16_REPEAT: u32 = 1 << 15;
+
+/// Most values a transfer of [`pio16_program`] can hold.
+const PIO16_MAX: usize = 1 << 15;
+
+/// Values buffered per transfer when the length isn't known up front.
+const PIO16_CHUNK: usize = 128;
+
+fn pio16_push(tx: &mut impl PioTx, word: u32) {
+    while !tx.write(word) {}
+}
+
+/// Sends `values` as [`pio16_program`] transfers.
+fn pio16_write(tx: &mut impl PioTx, values: &[u16]) {
+    for chunk in values.chunks(PIO16_MAX) {
+        pio16_push(tx, chunk.len() as u32 - 1);
+        for &value in chunk {
+            pio16_push(tx, value as u32);
+        }
+    }
+}
+
+fn pio16_write_iter(tx: &mut impl PioTx, mut iter: impl Iterator<Item = u16>) {
+    let mut buf = [0; PIO16_CHUNK];
+    loop {
+        let mut len = 0;
+        for (slot, value) in buf.iter_mut().zip(&mut iter) {
+            *slot = value;
+            len += 1;
+        }
+        pio16_write(tx, &buf[..len]);
+        if len < PIO16_CHUNK {
+            return;
+        }
+    }
+}
+
+/// Sends `data` over the 16-bit bus, where a word-wide bus has no byte
+/// order on the wire, so all of the 16-bit formats are sent as is.
+fn pio16_write_data(tx: &mut impl PioTx, data: DataFormat<'_>) -> Result {
+    match data {
+        DataFormat::U8(slice) => pio16_write_iter(tx, slice.iter().map(|&v| v as u16)),
+        DataFormat::U8Iter(iter) => pio16_write_iter(tx, iter.map(u16::from)),
+        DataFormat::U16(slice) => pio16_write(tx, slice),
+        DataFormat::U16BE(slice) => pio16_write(tx, slice),
+        DataFormat::U16LE(slice) => pio16_write(tx, slice),
+        DataFormat::U16BEIter(iter) => pio16_write_iter(tx, iter),
+        DataFormat::U16LEIter(iter) => pio16_write_iter(tx, iter),
+        _ => return Err(DisplayError::DataFormatNotImplemented),
+    }
+    Ok(())
+}
+
+/// Has the state machine send `pixel` `count` times if it is a single
+/// value, streams it from the CPU otherwise.
+fn pio16_send_repeated(tx: &mut impl PioTx, pixel: DataFormat<'_>, mut count: u32) -> Result {
+    let value = match pixel {
+        DataFormat::U8(&[value]) => value as u16,
+        DataFormat::U16(&[value]) => value,
+        DataFormat::U16BE(&mut [value]) => value,
+        DataFormat::U16LE(&mut [value]) => value,
+        DataFormat::U8(bytes) => {
+            let len = bytes.len() * count as usize;
+            pio16_write_iter(tx, bytes.iter().map(|&v| v as u16).cycle().take(len));
+            return Ok(());
+        }
+        _ => return Err(DisplayError::DataFormatNotImplemented),
+    };
+    while count > 0 {
+        let n = count.min(PIO16_MAX as u32);
+        pio16_push(tx, PIO16_REPEAT | (n - 1));
+        pio16_push(tx, value as u32);
+        count -= n;
+    }
+    Ok(())
+}
+
+pub struct Pio16BitBus<TX: PioTx, DC> {
+    tx: TX,
+    dc: DC,
+}
+
+impl<TX, DC> Pio16BitBus<TX, DC>
+where
+    TX: PioTx,
+    DC: OutputPin,
+{
+    pub fn new(tx: TX, dc: DC) -> Self {
+        Self { tx, dc }
+    }
+
+    /// Waits until the state machine has shifted out everything queued so
+    /// far, so DC can be toggled without cutting off the last values.
+    ///
+    /// Never returns if the state machine isn't running, see
+    /// [`flush_timeout`](Self::flush_timeout).
+    pub fn flush(&mut self) {
+        pio_flush(&mut self.tx);
+    }
+
+    /// Like [`flush`](Self::flush), giving up with a `BusWriteError` after
+    /// `timeout_us`.
+    pub fn flush_timeout(&mut self, delay_source: &mut impl DelayNs, timeout_us: u32) -> Result {
+        pio_flush_timeout(&mut self.tx, delay_source, timeout_us)
+    }
+
+    /// Every value takes a whole FIFO word, the PIO program shifts out
+    /// 16 bits per WR strobe.
+    pub fn write_data(&mut self, data: DataFormat<'_>) -> Result {
+        pio16_write_data(&mut self.tx, data)
+    }
+}
+
+impl<P, SM, DC> Pio16BitBus<Tx<(P, SM)>, DC>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+    DC: OutputPin,
+{
+    /// Installs [`pio16_program`] on `sm` with the finest timing that keeps
+    /// the write cycle at `sys_clk` above `min_write_cycle_ns`.
+    ///
+    /// `wr` and the sixteen data pins from `d0` must already be switched to
+    /// the PIO function. The running state machine is handed back for
+    /// [`PioReadBus`].
+    pub fn setup(
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        sys_clk: HertzU32,
+        min_write_cycle_ns: u32,
+        wr: u8,
+        d0: u8,
+        dc: DC,
+    ) -> SetupResult<(Self, BusStateMachine<P, SM>)> {
+        let timing = BusTiming::new(sys_clk, min_write_cycle_ns)?;
+        let program = pio16_program(&timing);
+        let (sm, tx) = start_bus_sm(pio, sm, &program, &timing, wr, d0, 16)?;
+        Ok((Self::new(tx, dc), sm))
+    }
+}
+
+impl<TX, DC> WriteOnlyDataCommand for Pio16BitBus<TX, DC>
+where
+    TX: PioTx,
+    DC: OutputPin,
+{
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        self.flush();
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.write_data(cmd)?;
+        Ok(())
+    }
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.flush();
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.write_data(buf)?;
+        Ok(())
+    }
+}
+
+impl<TX: PioTx, DC: OutputPin> BusPixelFormat for Pio16BitBus<TX, DC> {
+    const THREE_BYTE_PIXELS: bool = false;
+}
+
+/// Single-value pixels are repeated by the state machine, anything else is
+/// streamed from the CPU.
+impl<TX, DC> RepeatDataCommand for Pio16BitBus<TX, DC>
+where
+    TX: PioTx,
+    DC: OutputPin,
+{
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result {
+        self.flush();
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        pio16_send_repeated(&mut self.tx, pixel, count)
+    }
+}
+
+/// Interface pixel format, written to COLMOD (0x3A) by `init`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 16 bits per pixel, parallel interfaces only.
+    Rgb565,
+    /// 18 bits per pixel, the only format the SPI interface accepts.
+    Rgb666,
+}
+
+impl PixelFormat {
+    fn colmod(self) -> u8 {
+        match self {
+            PixelFormat::Rgb565 => 0x55,
+            PixelFormat::Rgb666 => 0x66,
+        }
+    }
+}
+
+/// Errors from `init`.
+#[derive(Debug)]
+pub enum InitError<E> {
+    Display(DisplayError),
+    /// The backlight pin failed to turn on, with its error.
+    Backlight(E),
+}
+
+impl<E> From<DisplayError> for InitError<E> {
+    fn from(e: DisplayError) -> Self {
+        InitError::Display(e)
+    }
+}
+
+/// ILI9488 driver, drawing in colour type `C`.
+///
+/// `C` is [`Rgb565`] unless switched with [`ILI9488::with_color_format`].
+/// `TE` is the pin wired to the tearing effect output, `()` until one is
+/// added with [`ILI9488::with_te_pin`].
+pub struct ILI9488<DI, RST, BL, C = Rgb565, TE = ()>
 where
     DI: WriteOnlyDataCommand,
     RST: OutputPin,
     BL: OutputPin,
+    C: ColorFormat,
 {
     di: DI,
     rst: Option<RST>,
     bl: Option<BL>,
+    pixel_format: PixelFormat,
+    orientation: Orientation,
 
     size_x: u16,
     size_y: u16,
+    /// Column and page ranges last sent, so unchanged ones can be skipped.
+    columns: Option<(u16, u16)>,
+    pages: Option<(u16, u16)>,
+    scroll: ScrollArea,
+    scroll_offset: u16,
+    power: PowerState,
+    te: TE,
+    color: PhantomData<C>,
 }
 
 impl<DI, RST, BL> ILI9488<DI, RST, BL>
@@ -96,104 +697,232 @@ where
             di,
             rst,
             bl,
+            pixel_format: PixelFormat::Rgb565,
+            orientation: Orientation::default(),
             size_x,
             size_y,
+            columns: None,
+            pages: None,
+            scroll: ScrollArea::full(size_x.max(size_y)),
+            scroll_offset: 0,
+            power: PowerState::RESET,
+            te: (),
+            color: PhantomData,
         }
     }
 
+    /// Selects the pixel format `init` programs, unless the bus needs
+    /// another one like [`SpiInterface`] does.
+    pub fn with_pixel_format(mut self, pixel_format: PixelFormat) -> Self {
+        self.pixel_format = pixel_format;
+        self
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Switches the colour type the display is drawn with, along with the
+    /// pixel format `init` programs for it.
+    ///
+    /// [`Rgb666`] and [`Rgb888`] use the 18-bit format, sent as three bytes
+    /// per pixel. That needs an 8-bit bus or [`SpiInterface`], on a 16-bit
+    /// bus they don't build.
+    ///
+    /// [`Rgb666`]: embedded_graphics::pixelcolor::Rgb666
+    /// [`Rgb888`]: embedded_graphics::pixelcolor::Rgb888
+    pub fn with_color_format<C2: ColorFormat>(self) -> ILI9488<DI, RST, BL, C2, TE>
+    where
+        DI: BusPixelFormat,
+    {
+        const {
+            assert!(
+                DI::THREE_BYTE_PIXELS || matches!(C2::PIXEL_FORMAT, PixelFormat::Rgb565),
+                "the bus can't send 18-bit pixels"
+            )
+        };
+
+        ILI9488 {
+            di: self.di,
+            rst: self.rst,
+            bl: self.bl,
+            pixel_format: C2::PIXEL_FORMAT,
+            orientation: self.orientation,
+            size_x: self.size_x,
+            size_y: self.size_y,
+            columns: self.columns,
+            pages: self.pages,
+            scroll: self.scroll,
+            scroll_offset: self.scroll_offset,
+            power: self.power,
+            te: self.te,
+            color: PhantomData,
+        }
+    }
+
+    pub fn di_mut(&mut self) -> &mut DI {
+        &mut self.di
+    }
+
     pub fn init_test(&mut self) -> Result {
         self.write_command(0x55)?;
         Ok(())
     }
 
-    pub fn init(&mut self, delay_source: &mut Delay) -> Result {
+    pub fn init(
+        &mut self,
+        delay_source: &mut impl DelayNs,
+    ) -> core::result::Result<(), InitError<BL::Error>>
+    where
+        DI: BusPixelFormat,
+    {
+        self.init_with(&InitRegisters::default(), &[], delay_source)
+    }
+
+    /// The init sequence behind `init` and [`ILI9488Builder::init`].
+    pub(crate) fn init_with(
+        &mut self,
+        regs: &InitRegisters,
+        raw: &[RawCommand<'_>],
+        delay_source: &mut impl DelayNs,
+    ) -> core::result::Result<(), InitError<BL::Error>>
+    where
+        DI: BusPixelFormat,
+    {
         self.hard_reset(delay_source);
 
         if let Some(bl) = self.bl.as_mut() {
-            bl.set_high().unwrap();
+            bl.set_high().map_err(InitError::Backlight)?;
         }
 
-        // Positive Gamma Control
-        self.write_reg(&[0xE0, 0x00, 0x03, 0x09, 0x08, 0x16, 0x0A, 0x3F, 0x78, 0x4C, 0x09, 0x0A, 0x08, 0x16, 0x1A, 0x0F])?;
-
-        // Negative Gamma Control
-        self.write_reg(&[0xE1, 0x00, 0x16, 0x19, 0x03, 0x0F, 0x05, 0x32, 0x45, 0x46, 0x04, 0x0E, 0x0D, 0x35, 0x37, 0x0F])?;
+        self.write_cmd_params(0xE0, &regs.gamma.positive)?; // Positive Gamma Control
+        self.write_cmd_params(0xE1, &regs.gamma.negative)?; // Negative Gamma Control
 
-        self.write_reg(&[0xC0, 0x17, 0x15])?;          // Power Control 1
-        self.write_reg(&[0xC1, 0x41])?;                // Power Control 2
-        self.write_reg(&[0xC5, 0x00, 0x12, 0x80])?;    // VCOM Control
-        self.write_reg(&[0x36, 0x28])?;                // Memory Access Control
-        self.write_reg(&[0x3A, 0x55])?;                // Pixel Interface Format RGB565 8080 16-bit
-        self.write_reg(&[0xB0, 0x00])?;                // Interface Mode Control
+        let (power, vcom, frame_rate) = (regs.power, regs.vcom, regs.frame_rate);
+        self.write_reg(&[0xC0, power.vreg1out, power.vreg2out])?; // Power Control 1
+        self.write_reg(&[0xC1, power.step_up])?; // Power Control 2
+        self.write_reg(&[0xC5, 0x00, vcom.level, vcom.nv_memory_bit()])?; // VCOM Control
+        self.write_reg(&[0x36, self.orientation.madctl()])?; // Memory Access Control
+        let pixel_format = DI::PIXEL_FORMAT.unwrap_or(self.pixel_format);
+        self.write_reg(&[0x3A, pixel_format.colmod()])?; // Pixel Interface Format
+        self.write_reg(&[0xB0, 0x00])?; // Interface Mode Control
+        self.write_reg(&[0xB1, frame_rate.frs_diva(), frame_rate.rtna])?; // Frame Rate Control
+        self.write_reg(&[0xB4, regs.inversion as u8])?; // Display Inversion Control
+        self.write_reg(&[0xB6, 0x02, 0x02, 0x3B])?; // Display Function Control
+        self.write_reg(&[0xB7, regs.entry_mode.bits()])?; // Entry Mode Set
+        self.write_reg(&[0xF7, 0xA9, 0x51, 0x2C, 0x82])?; // Adjust Control 3
+        if regs.invert_colors {
+            self.write_reg(&[0x21])?; // Display Inversion ON
+        }
 
-        // Frame Rate Control
-        // self.write_reg(&[0xB1, 0xD0, 0x11);              // 60Hz
-        self.write_reg(&[0xB1, 0xD0, 0x14])?;          // 90Hz
+        for &(cmd, params, delay_ms) in raw {
+            self.write_cmd_params(cmd, params)?;
+            delay_source.delay_ms(delay_ms);
+        }
 
-        self.write_reg(&[0xB4, 0x02])?;                // Display Inversion Control
-        self.write_reg(&[0xB6, 0x02, 0x02, 0x3B])?;    // Display Function Control
-        self.write_reg(&[0xB7, 0xC6])?;                // Entry Mode Set
-        self.write_reg(&[0xF7, 0xA9, 0x51, 0x2C, 0x82])?;  // Adjust Control 3
-        self.write_reg(&[0x11])?;                      // Exit Sleep
+        self.write_reg(&[0x11])?; // Exit Sleep
         delay_source.delay_ms(60);
-        self.write_reg(&[0x29])?;                      // Display on
+        self.write_reg(&[0x29])?; // Display on
 
         Ok(())
     }
 
-    pub fn hard_reset(&mut self, delay_source: &mut Delay) {
-        if let Some(rst) = self.rst.as_mut() {
-            rst.set_high().unwrap();
-            delay_source.delay_ms(10);
-            rst.set_low().unwrap();
-            delay_source.delay_ms(10);
-            rst.set_high().unwrap();
-            delay_source.delay_ms(10);
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Rewrites MADCTL for `orientation`. Switching between portrait and
+    /// landscape swaps the width and height reported by `size()`.
+    ///
+    /// GRAM is not redrawn, the current content shows up rotated until the
+    /// screen is drawn again.
+    pub fn set_orientation(&mut self, orientation: Orientation) -> Result {
+        self.write_reg(&[0x36, orientation.madctl()])?;
+        if orientation.is_landscape() != self.orientation.is_landscape() {
+            core::mem::swap(&mut self.size_x, &mut self.size_y);
         }
+        self.orientation = orientation;
+        Ok(())
     }
 
-    pub fn set_addr_win(&mut self, xs: u16, ys: u16, xe: u16, ye: u16) -> Result {
-        self.write_reg(&[
-            0x2A,
-            (xs >> 8) as u8,
-            (xs) as u8,
-            (xe >> 8) as u8,
-            (xe) as u8,
-        ])?;
-
-        self.write_reg(&[
-            0x2B,
-            (ys >> 8) as u8,
-            (ys) as u8,
-            (ye >> 8) as u8,
-            (ye) as u8,
-        ])?;
+    /// Pulses RST, if there is one, and forgets what the driver tracked
+    /// of the panel registers. Does nothing without a RST pin.
+    pub fn hard_reset(&mut self, delay_source: &mut impl DelayNs) {
+        let Some(rst) = self.rst.as_mut() else {
+            return;
+        };
+        rst.set_high().unwrap();
+        delay_source.delay_ms(10);
+        rst.set_low().unwrap();
+        delay_source.delay_ms(10);
+        rst.set_high().unwrap();
+        delay_source.delay_ms(10);
+
+        (self.columns, self.pages) = (None, None);
+        (self.scroll, self.scroll_offset) = (ScrollArea::full(self.lines()), 0);
+        self.power = PowerState::RESET;
+    }
 
+    pub fn set_addr_win(&mut self, xs: u16, ys: u16, xe: u16, ye: u16) -> Result {
+        self.set_window(xs, ys, xe, ye)?;
         self.write_reg(&[0x2C])?;
         Ok(())
     }
 
-    pub fn clear(&mut self, color: Rgb565) -> Result {
-        let size = (self.size_x as u32) * (self.size_y as u32);
-        self.set_addr_win(0, 0, self.size_x - 1, self.size_y - 1)?;
+    /// Sets the column and page address range without starting a write.
+    ///
+    /// A range that is already set is not sent again.
+    fn set_window(&mut self, xs: u16, ys: u16, xe: u16, ye: u16) -> Result {
+        if self.columns != Some((xs, xe)) {
+            self.write_reg(&[
+                0x2A,
+                (xs >> 8) as u8,
+                (xs) as u8,
+                (xe >> 8) as u8,
+                (xe) as u8,
+            ])?;
+            self.columns = Some((xs, xe));
+        }
 
-        for _ in 0..size {
-            self.write_pixels([color])?;
+        if self.pages != Some((ys, ye)) {
+            self.write_reg(&[
+                0x2B,
+                (ys >> 8) as u8,
+                (ys) as u8,
+                (ye >> 8) as u8,
+                (ye) as u8,
+            ])?;
+            self.pages = Some((ys, ye));
         }
         Ok(())
     }
 
+    pub fn clear(&mut self, color: C) -> Result {
+        let size = (self.size_x as usize) * (self.size_y as usize);
+        self.set_addr_win(0, 0, self.size_x - 1, self.size_y - 1)?;
+        self.write_pixels(core::iter::repeat_n(color, size))
+    }
+
     pub fn write_pixels<I>(&mut self, colors: I) -> Result
     where
-        I: IntoIterator<Item = Rgb565>,
+        I: IntoIterator<Item = C>,
     {
-        let mut iter = colors.into_iter().map(|c| c.into_storage());
-        let buf = DataFormat::U16LEIter(&mut iter);
-        self.di.send_data(buf)?;
-        Ok(())
+        C::send_pixels(&mut self.di, colors.into_iter())
     }
 
     pub fn write_command(&mut self, cmd: u8) -> Result {
+        // Whatever follows may change the address window behind our back.
+        match cmd {
+            0x01 => (self.columns, self.pages) = (None, None), // Software Reset
+            0x2A => self.columns = None,
+            0x2B => self.pages = None,
+            _ => {}
+        }
+        self.power.track(cmd);
         self.di.send_commands(DataFormat::U8(&[cmd]))?;
         Ok(())
     }
@@ -204,10 +933,625 @@ where
     }
 
     pub fn write_reg(&mut self, seq: &[u8]) -> Result {
-        self.write_command(seq[0])?;
-        for val in &seq[1..] {
+        self.write_cmd_params(seq[0], &seq[1..])
+    }
+
+    fn write_cmd_params(&mut self, cmd: u8, params: &[u8]) -> Result {
+        self.write_command(cmd)?;
+        for val in params {
             self.write_data(*val)?;
         }
         Ok(())
     }
 }
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: ReadDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Sends `cmd` and reads back `buf.len()` bytes, skipping the dummy
+    /// byte the controller puts in front of every read.
+    pub fn read_reg(&mut self, cmd: u8, buf: &mut [u8]) -> Result {
+        let mut dummy = [0];
+        self.write_command(cmd)?;
+        self.di.read_data(&mut dummy)?;
+        self.di.read_data(buf)?;
+        Ok(())
+    }
+
+    /// Read Display Identification Information (0x04): manufacturer ID,
+    /// module version ID and module ID.
+    pub fn read_id(&mut self) -> Result<[u8; 3]> {
+        let mut id = [0; 3];
+        self.read_reg(0x04, &mut id)?;
+        Ok(id)
+    }
+
+    /// Read ID4 (0xD3), the IC model. An ILI9488 answers `[0x00, 0x94, 0x88]`.
+    pub fn read_id4(&mut self) -> Result<[u8; 3]> {
+        let mut id = [0; 3];
+        self.read_reg(0xD3, &mut id)?;
+        Ok(id)
+    }
+
+    /// Read Display Status (0x09).
+    pub fn read_status(&mut self) -> Result<[u8; 4]> {
+        let mut status = [0; 4];
+        self.read_reg(0x09, &mut status)?;
+        Ok(status)
+    }
+
+    /// Read Display Power Mode (0x0A).
+    pub fn read_power_mode(&mut self) -> Result<u8> {
+        let mut mode = [0];
+        self.read_reg(0x0A, &mut mode)?;
+        Ok(mode[0])
+    }
+
+    /// Reads back the GRAM content of `area` (0x2E), row by row.
+    ///
+    /// The controller returns 18-bit pixels as three bytes on an 8-bit bus,
+    /// which are narrowed down to Rgb565.
+    pub fn read_pixels(&mut self, area: &Rectangle, buf: &mut [Rgb565]) -> Result
+    where
+        DI: Read8BitDataCommand,
+    {
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+        let screen = self.bounding_box();
+        if !screen.contains(area.top_left) || !screen.contains(bottom_right) {
+            return Err(DisplayError::OutOfBoundsError);
+        }
+        let count = (area.size.width * area.size.height) as usize;
+        let Some(buf) = buf.get_mut(..count) else {
+            return Err(DisplayError::OutOfBoundsError);
+        };
+
+        self.set_window(
+            area.top_left.x as u16,
+            area.top_left.y as u16,
+            bottom_right.x as u16,
+            bottom_right.y as u16,
+        )?;
+        self.write_command(0x2E)?;
+        self.di.read_data(&mut [0])?;
+
+        for pixel in buf {
+            let mut rgb = [0; 3];
+            self.di.read_data(&mut rgb)?;
+            *pixel = Rgb565::new(rgb[0] >> 3, rgb[1] >> 2, rgb[2] >> 3);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockInterface, MockTx, NoopPin};
+    use embedded_graphics::prelude::{OriginDimensions, Point, RgbColor, Size};
+
+    /// Runs the FIFO words through `pio8_program`, returning the bytes that
+    /// would be strobed onto the bus.
+    fn run_pio8(words: &[u32]) -> Vec<u8> {
+        let program = pio8_program(&BusTiming {
+            divisor: 1,
+            wr_low: 1,
+            wr_high: 1,
+        });
+        let bytes = run_program(&program, 32, 8, words);
+        bytes.into_iter().map(|v| v as u8).collect()
+    }
+
+    fn bytes_8bit(data: DataFormat<'_>) -> Vec<u8> {
+        let mut bus = Pio8BitBus::new(MockTx::default(), NoopPin);
+        bus.write_data(data).unwrap();
+        run_pio8(&bus.tx.words)
+    }
+
+    #[test]
+    fn pio8_u8() {
+        assert_eq!(bytes_8bit(DataFormat::U8(&[0x2A, 0x01])), [0x2A, 0x01]);
+        let mut iter = [0x12u8, 0x34].into_iter();
+        assert_eq!(bytes_8bit(DataFormat::U8Iter(&mut iter)), [0x12, 0x34]);
+    }
+
+    #[test]
+    fn pio8_u16_slices() {
+        let native = if cfg!(target_endian = "little") {
+            [0x34, 0x12]
+        } else {
+            [0x12, 0x34]
+        };
+        assert_eq!(bytes_8bit(DataFormat::U16(&[0x1234])), native);
+        assert_eq!(bytes_8bit(DataFormat::U16BE(&mut [0x1234])), [0x12, 0x34]);
+        assert_eq!(bytes_8bit(DataFormat::U16LE(&mut [0x1234])), [0x34, 0x12]);
+    }
+
+    #[test]
+    fn pio8_u16_iters() {
+        let mut iter = [0x1234u16, 0xABCD].into_iter();
+        assert_eq!(
+            bytes_8bit(DataFormat::U16BEIter(&mut iter)),
+            [0x12, 0x34, 0xAB, 0xCD]
+        );
+        let mut iter = [0x1234u16, 0xABCD].into_iter();
+        assert_eq!(
+            bytes_8bit(DataFormat::U16LEIter(&mut iter)),
+            [0x34, 0x12, 0xCD, 0xAB]
+        );
+    }
+
+    #[test]
+    fn pio8_packs_four_bytes_per_word() {
+        let mut bus = Pio8BitBus::new(MockTx::default(), NoopPin);
+        bus.write_data(DataFormat::U8(&[1, 2, 3, 4, 5])).unwrap();
+        assert_eq!(bus.tx.words, [4, 0x0403_0201, 0x05]);
+
+        bus.tx.words.clear();
+        bus.write_data(DataFormat::U8(&[1, 2, 3, 4])).unwrap();
+        assert_eq!(bus.tx.words, [3, 0x0403_0201, 0]);
+    }
+
+    #[test]
+    fn pio8_long_iter_is_chunked() {
+        let mut iter = (0..1000u32).map(|v| v as u8);
+        let expected: Vec<u8> = (0..1000u32).map(|v| v as u8).collect();
+        assert_eq!(bytes_8bit(DataFormat::U8Iter(&mut iter)), expected);
+
+        let long = [0xA5u8; PIO8_CHUNK * 2 + 1];
+        assert_eq!(bytes_8bit(DataFormat::U8(&long)), long);
+    }
+
+    /// Delay field of an instruction, below the single side-set bit.
+    fn delay(instr: u16) -> u16 {
+        instr >> 8 & 0xF
+    }
+
+    #[test]
+    fn programs_stretch_wr_strobe() {
+        let timing = BusTiming {
+            divisor: 1,
+            wr_low: 4,
+            wr_high: 3,
+        };
+        let program = pio8_program(&timing);
+        assert_eq!(program.code.len(), 13);
+        // Repeat loop, then byte loop: WR low while the data is put out.
+        for pc in [5, 7, 10] {
+            assert_eq!(delay(program.code[pc]), 3, "pc {pc}"); // mov/out pins  side 0
+            assert_eq!(program.code[pc] >> 12 & 1, 0, "pc {pc}");
+        }
+        for pc in [6, 8, 11] {
+            assert_eq!(delay(program.code[pc]), 2, "pc {pc}"); // side 1
+            assert_eq!(program.code[pc] >> 12 & 1, 1, "pc {pc}");
+        }
+
+        let program = pio16_program(&timing);
+        assert_eq!(program.code.len(), 9);
+        // Repeat loop, then value loop.
+        for pc in [4, 7] {
+            assert_eq!(delay(program.code[pc]), 3, "pc {pc}"); // mov/out pins  side 0
+            assert_eq!(program.code[pc] >> 12 & 1, 0, "pc {pc}");
+        }
+        for pc in [5, 8] {
+            assert_eq!(delay(program.code[pc]), 2, "pc {pc}"); // side 1
+            assert_eq!(program.code[pc] >> 12 & 1, 1, "pc {pc}");
+        }
+    }
+
+    #[test]
+    fn pio8_repeats_pairs_in_the_state_machine() {
+        let mut bus = Pio8BitBus::new(MockTx::default(), NoopPin);
+        bus.send_repeated(DataFormat::U16BE(&mut [0xF81F]), 1000)
+            .unwrap();
+        assert_eq!(bus.tx.words, [PIO8_REPEAT | 999, 0x1FF8]);
+
+        // Everything queued behind a repeat still comes out in order.
+        bus.send_repeated(DataFormat::U16LE(&mut [0xABCD]), 2)
+            .unwrap();
+        bus.send_data(DataFormat::U8(&[0x2C])).unwrap();
+        bus.send_repeated(DataFormat::U8(&[0x12, 0x34]), 2).unwrap();
+        bus.send_data(DataFormat::U8(&[0x29])).unwrap();
+        let mut expected = [0xF8, 0x1F].repeat(1000);
+        expected.extend([0xCD, 0xAB, 0xCD, 0xAB, 0x2C, 0x12, 0x34, 0x12, 0x34, 0x29]);
+        assert_eq!(run_pio8(&bus.tx.words), expected);
+    }
+
+    #[test]
+    fn flush_gives_up_on_a_stopped_state_machine() {
+        let mut bus = Pio8BitBus::new(MockTx::default(), NoopPin);
+        bus.write_data(DataFormat::U8(&[0x2C])).unwrap();
+        let mut delay = MockDelay::default();
+        bus.flush_timeout(&mut delay, 10).unwrap();
+
+        bus.tx.stopped = true;
+        let mut delay = MockDelay::default();
+        let error = bus.flush_timeout(&mut delay, 10).unwrap_err();
+        assert!(matches!(error, DisplayError::BusWriteError));
+        assert_eq!(delay.total_ns, 10_000);
+    }
+
+    #[test]
+    fn pio8_streams_longer_pixels() {
+        let mut bus = Pio8BitBus::new(MockTx::default(), NoopPin);
+        bus.send_repeated(DataFormat::U8(&[1, 2, 3]), 3).unwrap();
+        assert_eq!(run_pio8(&bus.tx.words), [1, 2, 3, 1, 2, 3, 1, 2, 3]);
+
+        bus.tx.words.clear();
+        bus.send_repeated(DataFormat::U8(&[1, 2]), 0).unwrap();
+        assert_eq!(bus.tx.words, []);
+    }
+
+    /// Runs `program` on a model of a state machine with right shift and
+    /// autopull at `threshold`, returning the `width` low pin bits latched
+    /// on every rising WR edge.
+    ///
+    /// All of `words` sit in the FIFO from the start, so autopull refills
+    /// the OSR as soon as an OUT empties it, like a bus that keeps ahead of
+    /// the state machine. The run ends when an OUT stalls on the empty FIFO.
+    pub(crate) fn run_program(
+        program: &pio::Program<{ pio::RP2040_MAX_PROGRAM_SIZE }>,
+        threshold: u32,
+        width: u32,
+        words: &[u32],
+    ) -> Vec<u32> {
+        let mut fifo: std::collections::VecDeque<u32> = words.iter().copied().collect();
+        let (mut osr, mut count) = (0u32, 32);
+        let (mut x, mut y, mut isr, mut pins) = (0u32, 0u32, 0u32, 0u32);
+        let mut wr = true;
+        let mut latched = Vec::new();
+        let mut pc = program.wrap.target as usize;
+        loop {
+            let instr = program.code[pc];
+            let side = instr >> 12 & 1 == 1;
+            if side && !wr {
+                latched.push(pins & ((1 << width) - 1));
+            }
+            wr = side;
+            let (operand, bits) = (instr >> 5 & 7, (instr & 0x1F) as u32);
+            let mut next = pc + 1;
+            match instr >> 13 {
+                0b000 => {
+                    let taken = match operand {
+                        0b000 => true,
+                        0b001 => x == 0,
+                        0b010 => {
+                            let nonzero = x != 0;
+                            x = x.wrapping_sub(1);
+                            nonzero
+                        }
+                        0b011 => y == 0,
+                        _ => unimplemented!("jmp condition {operand:#b}"),
+                    };
+                    if taken {
+                        next = bits as usize;
+                    }
+                }
+                0b011 => {
+                    if count >= threshold {
+                        let Some(word) = fifo.pop_front() else {
+                            return latched;
+                        };
+                        (osr, count) = (word, 0);
+                    }
+                    let n = if bits == 0 { 32 } else { bits };
+                    let value = osr & (u32::MAX >> (32 - n));
+                    osr = osr.checked_shr(n).unwrap_or(0);
+                    count = (count + n).min(32);
+                    match operand {
+                        0b000 => pins = value,
+                        0b001 => x = value,
+                        0b010 => y = value,
+                        0b011 => {}
+                        0b110 => isr = value,
+                        _ => unimplemented!("out destination {operand:#b}"),
+                    }
+                    if count >= threshold {
+                        if let Some(word) = fifo.pop_front() {
+                            (osr, count) = (word, 0);
+                        }
+                    }
+                }
+                0b101 => {
+                    assert_eq!(instr >> 3 & 3, 0, "mov operation");
+                    let value = match instr & 7 {
+                        0b001 => x,
+                        0b010 => y,
+                        0b011 => 0,
+                        0b110 => isr,
+                        0b111 => osr,
+                        source => unimplemented!("mov source {source:#b}"),
+                    };
+                    match operand {
+                        0b000 => pins = value,
+                        0b001 => x = value,
+                        0b010 => y = value,
+                        0b110 => isr = value,
+                        0b111 => (osr, count) = (value, 0),
+                        _ => unimplemented!("mov destination {operand:#b}"),
+                    }
+                }
+                op => unimplemented!("opcode {op:#b}"),
+            }
+            pc = if pc == program.wrap.source as usize && next == pc + 1 {
+                program.wrap.target as usize
+            } else {
+                next
+            };
+        }
+    }
+
+    /// Runs the FIFO words through `pio16_program`, returning the values
+    /// that would be strobed onto the bus.
+    pub(crate) fn run_pio16(words: &[u32]) -> Vec<u16> {
+        let program = pio16_program(&BusTiming {
+            divisor: 1,
+            wr_low: 1,
+            wr_high: 1,
+        });
+        let values = run_program(&program, 16, 16, words);
+        values.into_iter().map(|v| v as u16).collect()
+    }
+
+    #[test]
+    fn pio16_one_word_per_value() {
+        let mut bus = Pio16BitBus::new(MockTx::default(), NoopPin);
+        bus.write_data(DataFormat::U8(&[0x2C])).unwrap();
+        let mut iter = [0xF800u16, 0x07E0].into_iter();
+        bus.write_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+        assert_eq!(bus.tx.words, [0, 0x2C, 1, 0xF800, 0x07E0]);
+    }
+
+    #[test]
+    fn pio16_long_writes_are_split() {
+        let mut bus = Pio16BitBus::new(MockTx::default(), NoopPin);
+        let long: Vec<u16> = (0..PIO16_MAX as u32 + 3).map(|v| v as u16).collect();
+        bus.write_data(DataFormat::U16(&long)).unwrap();
+        assert_eq!(bus.tx.words[0], PIO16_MAX as u32 - 1);
+        assert_eq!(run_pio16(&bus.tx.words), long);
+
+        bus.tx.words.clear();
+        let mut iter = long.iter().copied();
+        bus.write_data(DataFormat::U16LEIter(&mut iter)).unwrap();
+        assert_eq!(run_pio16(&bus.tx.words), long);
+    }
+
+    #[test]
+    fn pio16_repeats_values_in_the_state_machine() {
+        let mut bus = Pio16BitBus::new(MockTx::default(), NoopPin);
+        bus.send_repeated(DataFormat::U16BE(&mut [0xF81F]), 153_600)
+            .unwrap();
+        let header = |n: u32| PIO16_REPEAT | (n - 1);
+        let expected = [
+            header(32768),
+            0xF81F,
+            header(32768),
+            0xF81F,
+            header(32768),
+            0xF81F,
+            header(32768),
+            0xF81F,
+            header(22528),
+            0xF81F,
+        ];
+        assert_eq!(bus.tx.words, expected);
+
+        // Everything queued behind the chunks still comes out in order.
+        bus.send_data(DataFormat::U8(&[0x2C])).unwrap();
+        bus.send_repeated(DataFormat::U16(&[0x07E0]), 3).unwrap();
+        bus.send_repeated(DataFormat::U8(&[0xFC, 0x00, 0x80]), 2)
+            .unwrap();
+        let mut values = vec![0xF81F; 153_600];
+        values.extend([
+            0x2C, 0x07E0, 0x07E0, 0x07E0, 0xFC, 0x00, 0x80, 0xFC, 0x00, 0x80,
+        ]);
+        assert_eq!(run_pio16(&bus.tx.words), values);
+    }
+
+    /// Logs what is written and answers reads from a canned byte stream.
+    #[derive(Default)]
+    struct FakeReadBus {
+        commands: Vec<u8>,
+        data: Vec<u8>,
+        reads: std::collections::VecDeque<u8>,
+    }
+
+    impl WriteOnlyDataCommand for FakeReadBus {
+        fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+            if let DataFormat::U8(cmd) = cmd {
+                self.commands.extend_from_slice(cmd);
+            }
+            Ok(())
+        }
+        fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+            if let DataFormat::U8(buf) = buf {
+                self.data.extend_from_slice(buf);
+            }
+            Ok(())
+        }
+    }
+
+    impl ReadDataCommand for FakeReadBus {
+        fn read_data(&mut self, buf: &mut [u8]) -> Result {
+            for byte in buf {
+                *byte = self.reads.pop_front().ok_or(DisplayError::BusWriteError)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Read8BitDataCommand for FakeReadBus {}
+
+    fn read_display(reads: &[u8]) -> ILI9488<FakeReadBus, NoopPin, NoopPin> {
+        let bus = FakeReadBus {
+            reads: reads.iter().copied().collect(),
+            ..Default::default()
+        };
+        ILI9488::new(bus, None, None, 480, 320)
+    }
+
+    #[test]
+    fn read_id_skips_dummy_byte() {
+        let mut display = read_display(&[0xFF, 0x00, 0x94, 0x88]);
+        assert_eq!(display.read_id4().unwrap(), [0x00, 0x94, 0x88]);
+        assert_eq!(display.di.commands, [0xD3]);
+
+        let mut display = read_display(&[0xFF, 0x9C]);
+        assert_eq!(display.read_power_mode().unwrap(), 0x9C);
+        assert_eq!(display.di.commands, [0x0A]);
+    }
+
+    #[test]
+    fn read_pixels_narrows_rgb666() {
+        let mut display = read_display(&[0xFF, 0xFC, 0x00, 0x00, 0x00, 0xFC, 0xF8]);
+        let mut pixels = [Rgb565::BLACK; 2];
+        let area = Rectangle::new(Point::new(10, 20), Size::new(2, 1));
+        display.read_pixels(&area, &mut pixels).unwrap();
+
+        assert_eq!(pixels, [Rgb565::RED, Rgb565::new(0, 0x3F, 0x1F)]);
+        assert_eq!(display.di.commands, [0x2A, 0x2B, 0x2E]);
+        assert_eq!(display.di.data, [0, 10, 0, 11, 0, 20, 0, 20]);
+    }
+
+    #[test]
+    fn read_pixels_rejects_area_off_screen() {
+        let mut display = read_display(&[]);
+        let mut pixels = [Rgb565::BLACK; 4];
+        let area = Rectangle::new(Point::new(479, 0), Size::new(2, 2));
+        assert!(display.read_pixels(&area, &mut pixels).is_err());
+    }
+
+    fn mock_display() -> ILI9488<MockInterface, NoopPin, NoopPin> {
+        ILI9488::new(MockInterface::new(), None, Some(NoopPin), 480, 320)
+    }
+
+    #[test]
+    fn init_sequence() {
+        let mut display = mock_display();
+        let mut delay = MockDelay::default();
+        display.init(&mut delay).unwrap();
+
+        assert_eq!(
+            display.di.opcodes(),
+            [
+                0xE0, 0xE1, 0xC0, 0xC1, 0xC5, 0x36, 0x3A, 0xB0, 0xB1, 0xB4, 0xB6, 0xB7, 0xF7, 0x11,
+                0x29
+            ]
+        );
+        display
+            .di
+            .expect()
+            .skip_to(0xC0)
+            .command(0xC0, &[0x17, 0x15])
+            .command(0xC1, &[0x41])
+            .command(0xC5, &[0x00, 0x12, 0x80])
+            .command(0x36, &[0x28])
+            .command(0x3A, &[0x55])
+            .skip_to(0x11)
+            .command(0x11, &[])
+            .command(0x29, &[])
+            .end();
+        // Sleep out needs time before the display can be switched on.
+        assert!(delay.total_ns >= 5_000_000);
+    }
+
+    #[test]
+    fn init_programs_pixel_format() {
+        let mut display = mock_display().with_pixel_format(PixelFormat::Rgb666);
+        display.init(&mut MockDelay::default()).unwrap();
+        assert_eq!(display.di.last(0x3A).unwrap().data, [0x66]);
+    }
+
+    #[test]
+    fn set_addr_win_starts_ramwr() {
+        let mut display = mock_display();
+        display.set_addr_win(10, 20, 479, 300).unwrap();
+        display
+            .di
+            .expect()
+            .command(0x2A, &[0x00, 10, 0x01, 0xDF])
+            .command(0x2B, &[0x00, 20, 0x01, 0x2C])
+            .command(0x2C, &[])
+            .end();
+
+        display.di.clear();
+        display.set_addr_win(0, 0, 0, 0).unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 0, 0)
+            .command(0x2C, &[])
+            .end();
+    }
+
+    #[test]
+    fn set_orientation_swaps_size() {
+        let mut display = mock_display();
+        display
+            .set_orientation(Orientation::Portrait(false))
+            .unwrap();
+        assert_eq!(display.size(), Size::new(320, 480));
+        display
+            .set_orientation(Orientation::PortraitFlipped(true))
+            .unwrap();
+        assert_eq!(display.size(), Size::new(320, 480));
+        display
+            .set_orientation(Orientation::LandscapeFlipped(false))
+            .unwrap();
+        assert_eq!(display.size(), Size::new(480, 320));
+        assert_eq!(display.di.opcodes(), [0x36, 0x36, 0x36]);
+        assert_eq!(display.di.last(0x36).unwrap().data, [0xE8]);
+
+        display.clear(Rgb565::BLACK).unwrap();
+        display
+            .di
+            .expect()
+            .skip_to(0x2A)
+            .window(0, 0, 479, 319)
+            .ramwr(480 * 320)
+            .end();
+    }
+
+    #[test]
+    fn init_keeps_orientation() {
+        let mut display = mock_display();
+        display
+            .set_orientation(Orientation::Portrait(false))
+            .unwrap();
+        display.di.clear();
+        display.init(&mut MockDelay::default()).unwrap();
+        assert_eq!(display.di.last(0x36).unwrap().data, [0x48]);
+    }
+
+    #[test]
+    fn clear_fills_whole_screen() {
+        let mut display = mock_display();
+        display.clear(Rgb565::RED).unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 479, 319)
+            .ramwr_solid(Rgb565::RED, 480 * 320)
+            .end();
+        // A byte for every CASET and PASET parameter, then all pixels in one.
+        assert_eq!(display.di.data_writes(), 8 + 1);
+    }
+
+    #[test]
+    fn clear_repeated_lets_the_bus_repeat() {
+        let mut display = mock_display();
+        display.clear_repeated(Rgb565::RED).unwrap();
+        display
+            .di
+            .expect()
+            .window(0, 0, 479, 319)
+            .ramwr_solid(Rgb565::RED, 480 * 320)
+            .end();
+        assert_eq!(display.di.repeats(), 1);
+    }
+}
@@ -16,7 +16,7 @@ use hal::{
     gpio::{FunctionPio0, Pin},
     pac,
     pac::vreg_and_chip_reset::vreg::VSEL_A,
-    pio::{Buffers, PIOExt, ShiftDirection},
+    pio::PIOExt,
     pll::{common_configs::PLL_USB_48MHZ, setup_pll_blocking},
     sio::Sio,
     vreg::set_voltage,
@@ -40,7 +40,7 @@ use embedded_graphics::{
     },
     text::{Alignment, Text},
 };
-use lib::{overclock, Pio8BitBus, ILI9488};
+use lib::{overclock, touch::Tsc2007, Pio8BitBus, ILI9488, ILI9488_T_WC_NS};
 use overclock::overclock_configs::PLL_SYS_240MHZ;
 
 #[entry]
@@ -92,14 +92,6 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
-    let program = pio_proc::pio_asm!(
-        ".side_set 1"
-        ".wrap_target",
-        "   out pins, 16    side 0",
-        "   nop             side 1",
-        ".wrap"
-    );
-
     let wr: Pin<_, FunctionPio0, _> = pins.gpio19.into_function();
     let wr_pin_id = wr.id().num;
 
@@ -108,46 +100,30 @@ fn main() -> ! {
     let bl = pins.gpio28.into_push_pull_output();
 
     let lcd_d0: Pin<_, FunctionPio0, _> = pins.gpio0.into_function();
-    let lcd_d1: Pin<_, FunctionPio0, _> = pins.gpio1.into_function();
-    let lcd_d2: Pin<_, FunctionPio0, _> = pins.gpio2.into_function();
-    let lcd_d3: Pin<_, FunctionPio0, _> = pins.gpio3.into_function();
-    let lcd_d4: Pin<_, FunctionPio0, _> = pins.gpio4.into_function();
-    let lcd_d5: Pin<_, FunctionPio0, _> = pins.gpio5.into_function();
-    let lcd_d6: Pin<_, FunctionPio0, _> = pins.gpio6.into_function();
-    let lcd_d7: Pin<_, FunctionPio0, _> = pins.gpio7.into_function();
+    let _lcd_d1: Pin<_, FunctionPio0, _> = pins.gpio1.into_function();
+    let _lcd_d2: Pin<_, FunctionPio0, _> = pins.gpio2.into_function();
+    let _lcd_d3: Pin<_, FunctionPio0, _> = pins.gpio3.into_function();
+    let _lcd_d4: Pin<_, FunctionPio0, _> = pins.gpio4.into_function();
+    let _lcd_d5: Pin<_, FunctionPio0, _> = pins.gpio5.into_function();
+    let _lcd_d6: Pin<_, FunctionPio0, _> = pins.gpio6.into_function();
+    let _lcd_d7: Pin<_, FunctionPio0, _> = pins.gpio7.into_function();
 
     let lcd_d0_pin_id = lcd_d0.id().num;
 
-    let pindirs = [
-        (wr_pin_id, hal::pio::PinDir::Output),
-        (lcd_d0.id().num, hal::pio::PinDir::Output),
-        (lcd_d1.id().num, hal::pio::PinDir::Output),
-        (lcd_d2.id().num, hal::pio::PinDir::Output),
-        (lcd_d3.id().num, hal::pio::PinDir::Output),
-        (lcd_d4.id().num, hal::pio::PinDir::Output),
-        (lcd_d5.id().num, hal::pio::PinDir::Output),
-        (lcd_d6.id().num, hal::pio::PinDir::Output),
-        (lcd_d7.id().num, hal::pio::PinDir::Output),
-    ];
-
     let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
-    let installed = pio.install(&program.program).unwrap();
-    let (int, frac) = (1, 0); // as slow as possible (0 is interpreted as 65536)
-    let (mut sm, _, tx) = rp2040_hal::pio::PIOBuilder::from_installed_program(installed)
-        .side_set_pin_base(wr_pin_id)
-        .out_pins(lcd_d0_pin_id, 8)
-        .buffers(Buffers::OnlyTx)
-        .clock_divisor_fixed_point(int, frac)
-        .out_shift_direction(ShiftDirection::Right)
-        .autopull(true)
-        .pull_threshold(8)
-        .build(sm0);
-    sm.set_pindirs(pindirs);
-    sm.start();
+    let (di, _sm) = Pio8BitBus::setup(
+        &mut pio,
+        sm0,
+        clocks.system_clock.freq(),
+        ILI9488_T_WC_NS,
+        wr_pin_id,
+        lcd_d0_pin_id,
+        dc,
+    )
+    .unwrap();
 
     info!("PIO block setuped");
 
-    let di = Pio8BitBus::new(tx, dc);
     let mut display = ILI9488::new(di, Some(rst), Some(bl), 480, 320);
     display.init(&mut delay).unwrap();
 
@@ -230,8 +206,8 @@ fn main() -> ! {
     );
 
     let irq_pin = pins.gpio21.into_pull_up_input();
-    let mut touch = tsc2007::TSC2007::new(irq_pin, i2c).unwrap();
-    let _ = touch.init(&mut delay);
+    let mut touch = Tsc2007::new(irq_pin, i2c);
+    touch.init().unwrap();
 
     loop {
         asm::wfi();
@@ -245,89 +221,5 @@ fn main() -> ! {
     }
 }
 
-mod tsc2007 {
-    use cortex_m::delay::Delay;
-    use defmt::info;
-    use embedded_hal::digital::{InputPin, OutputPin};
-    use embedded_hal::i2c::I2c;
-
-    const TSC2007_DEF_ADDR: u8   = 0x48;
-    const TSC2007_CMD_READ_X: u8 = 0xC0;
-    const TSC2007_CMD_READ_Y: u8 = 0xD0;
-
-    pub struct TSC2007<IRQ: InputPin, I2C: I2c> {
-        irq: IRQ,
-        i2c: I2C,
-        addr: u8,
-    }
-
-    impl<PinE, IRQ: InputPin<Error = PinE>, I2C: I2c>
-        TSC2007<IRQ, I2C>
-    {
-        pub fn new(irq: IRQ, i2c: I2C) -> Result<Self, PinE> {
-            Ok(Self {
-                irq,
-                i2c,
-                addr: TSC2007_DEF_ADDR,
-            })
-        }
-
-        pub fn init(&mut self, delay_source: &mut Delay) -> Result<(), Error<PinE, I2C::Error>> {
-            Ok(())
-        }
-
-        pub fn read_reg(&mut self, reg: u8) -> Result<u8, I2C::Error> {
-            let mut readbuf: [u8; 1] = [0];
-            self.i2c.write_read(self.addr, &[reg], &mut readbuf)?;
-            Ok(readbuf[0])
-        }
-
-        pub fn read_reg_16(&mut self, reg: u8) -> Result<u16, I2C::Error> {
-            let mut readbuf: [u8; 2] = [0; 2];
-            self.i2c.write_read(self.addr, &[reg], &mut readbuf)?;
-            Ok((readbuf[0] as u16) << 8 | (readbuf[1] as u16))
-        }
-
-        // pub fn write_reg(&mut self, reg: u8, val: u8) -> Result<(), I2C::Error> {
-        //     Ok(())
-        // }
-
-        pub fn is_pressed(&mut self) -> Result<bool, PinE> {
-            self.irq.is_low()
-        }
-
-        pub fn read_x(&mut self) -> Result<u16, I2C::Error> {
-            Ok(self.read_reg_16(TSC2007_CMD_READ_X)?)
-        }
-
-        pub fn read_y(&mut self) -> Result<u16, I2C::Error> {
-            Ok(320 - (self.read_reg_16(TSC2007_CMD_READ_Y)? & 0x1fff))
-        }
-
-        pub fn read(&mut self) -> Result<Option<(u16, u16)>, Error<PinE, I2C::Error>> {
-            match self.is_pressed() {
-                Ok(pressed) => {
-                    if !pressed {
-                        Ok(None)
-                    } else {
-                        Ok(Some((
-                            self.read_x().unwrap(),
-                            self.read_y().unwrap(),
-                        )))
-                    }
-                }
-                Err(e) => {
-                    Ok(None)
-                }
-            }
-        }
-    }
-
-    pub enum Error<PinE, TransferE> {
-        Pin(PinE),
-        I2C(TransferE),
-    }
-}
-
 
 // End of file
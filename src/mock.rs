@@ -0,0 +1,466 @@
+//! Host-side stand-ins for the display bus, used by the unit tests.
+
+use core::convert::Infallible;
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::IntoStorage};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+    i2c::{self, I2c, Operation},
+};
+use std::{cell::Cell, collections::VecDeque, rc::Rc};
+
+use crate::{touch::RawTouch, BusPixelFormat, PioTx, RepeatDataCommand};
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// A command byte and all data sent after it, in bus order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub cmd: u8,
+    pub data: Vec<u8>,
+}
+
+/// [`WriteOnlyDataCommand`] that records the command stream.
+///
+/// 16-bit data is stored the way an 8-bit bus would put it on the wire, so
+/// a Rgb565 pixel sent as `U16BEIter` shows up as its two big-endian bytes.
+/// Consecutive data writes are merged into the command they follow.
+#[derive(Default)]
+pub struct MockInterface {
+    commands: Vec<Command>,
+    data_writes: usize,
+    repeats: usize,
+}
+
+impl MockInterface {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Just the command bytes, in order.
+    pub fn opcodes(&self) -> Vec<u8> {
+        self.commands.iter().map(|c| c.cmd).collect()
+    }
+
+    /// The last occurrence of `cmd`.
+    pub fn last(&self, cmd: u8) -> Option<&Command> {
+        self.commands.iter().rev().find(|c| c.cmd == cmd)
+    }
+
+    /// Number of `send_data` and `send_repeated` calls.
+    pub fn data_writes(&self) -> usize {
+        self.data_writes
+    }
+
+    /// Number of `send_repeated` calls.
+    pub fn repeats(&self) -> usize {
+        self.repeats
+    }
+
+    /// Bytes put on the bus so far, command bytes included.
+    pub fn bytes(&self) -> usize {
+        self.commands.iter().map(|c| 1 + c.data.len()).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Starts matching the recorded stream from the first command.
+    pub fn expect(&self) -> Expect<'_> {
+        Expect {
+            commands: &self.commands,
+            pos: 0,
+        }
+    }
+
+    fn push_data(&mut self, bytes: impl Iterator<Item = u8>) {
+        let Some(last) = self.commands.last_mut() else {
+            panic!("data sent before any command");
+        };
+        last.data.extend(bytes);
+    }
+}
+
+impl WriteOnlyDataCommand for MockInterface {
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        let DataFormat::U8(bytes) = cmd else {
+            return Err(DisplayError::DataFormatNotImplemented);
+        };
+        for &cmd in bytes {
+            self.commands.push(Command {
+                cmd,
+                data: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.data_writes += 1;
+        let bytes = bus_bytes(buf)?;
+        self.push_data(bytes.into_iter());
+        Ok(())
+    }
+}
+
+/// Records the pixel repeated `count` times, as if it was sent that often.
+impl BusPixelFormat for MockInterface {}
+
+impl RepeatDataCommand for MockInterface {
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result {
+        self.data_writes += 1;
+        self.repeats += 1;
+        let bytes = bus_bytes(pixel)?;
+        let len = bytes.len() * count as usize;
+        self.push_data(bytes.into_iter().cycle().take(len));
+        Ok(())
+    }
+}
+
+/// `data` in the byte order of an 8-bit bus.
+fn bus_bytes(data: DataFormat<'_>) -> Result<Vec<u8>> {
+    Ok(match data {
+        DataFormat::U8(slice) => slice.to_vec(),
+        DataFormat::U8Iter(iter) => iter.collect(),
+        DataFormat::U16(slice) => slice.iter().flat_map(|v| v.to_ne_bytes()).collect(),
+        DataFormat::U16BE(slice) => slice.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        DataFormat::U16LE(slice) => slice.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        DataFormat::U16BEIter(iter) => iter.flat_map(u16::to_be_bytes).collect(),
+        DataFormat::U16LEIter(iter) => iter.flat_map(u16::to_le_bytes).collect(),
+        _ => return Err(DisplayError::DataFormatNotImplemented),
+    })
+}
+
+/// Cursor over a recorded command stream. Every step checks the next
+/// command and panics with the offending entry if it doesn't match.
+pub struct Expect<'a> {
+    commands: &'a [Command],
+    pos: usize,
+}
+
+impl<'a> Expect<'a> {
+    #[track_caller]
+    fn next(&mut self, what: &str) -> &'a Command {
+        let Some(command) = self.commands.get(self.pos) else {
+            panic!("expected {what} at #{}, stream ended", self.pos);
+        };
+        self.pos += 1;
+        command
+    }
+
+    /// `cmd` with exactly these parameter bytes.
+    #[track_caller]
+    pub fn command(mut self, cmd: u8, data: &[u8]) -> Self {
+        let at = self.pos;
+        let command = self.next(&format!("{cmd:#04X}"));
+        assert_eq!(
+            (command.cmd, command.data.as_slice()),
+            (cmd, data),
+            "command #{at}"
+        );
+        self
+    }
+
+    /// Skips ahead to the next `cmd`, whatever its parameters.
+    #[track_caller]
+    pub fn skip_to(mut self, cmd: u8) -> Self {
+        while self.next(&format!("{cmd:#04X}")).cmd != cmd {}
+        self.pos -= 1;
+        self
+    }
+
+    /// CASET (0x2A) from `xs` to `xe`.
+    #[track_caller]
+    pub fn caset(self, xs: u16, xe: u16) -> Self {
+        self.command(0x2A, &range(xs, xe))
+    }
+
+    /// PASET (0x2B) from `ys` to `ye`.
+    #[track_caller]
+    pub fn paset(self, ys: u16, ye: u16) -> Self {
+        self.command(0x2B, &range(ys, ye))
+    }
+
+    /// CASET and PASET, like `ILI9488::set_addr_win` sends them.
+    #[track_caller]
+    pub fn window(self, xs: u16, ys: u16, xe: u16, ye: u16) -> Self {
+        self.caset(xs, xe).paset(ys, ye)
+    }
+
+    /// RAMWR (0x2C) carrying `count` Rgb565 pixels.
+    #[track_caller]
+    pub fn ramwr(mut self, count: usize) -> Self {
+        let at = self.pos;
+        let command = self.next("RAMWR");
+        assert_eq!(command.cmd, 0x2C, "command #{at}");
+        assert_eq!(command.data.len(), count * 2, "pixel bytes of #{at}");
+        self
+    }
+
+    /// RAMWR (0x2C) carrying exactly `pixels`.
+    #[track_caller]
+    pub fn ramwr_pixels(self, pixels: &[Rgb565]) -> Self {
+        let data: Vec<u8> = pixels
+            .iter()
+            .flat_map(|p| p.into_storage().to_be_bytes())
+            .collect();
+        self.command(0x2C, &data)
+    }
+
+    /// RAMWR (0x2C) carrying `count` pixels of `color`.
+    #[track_caller]
+    pub fn ramwr_solid(self, color: Rgb565, count: usize) -> Self {
+        self.ramwr_pixels(&vec![color; count])
+    }
+
+    /// Asserts nothing was sent after the matched commands.
+    #[track_caller]
+    pub fn end(self) {
+        assert_eq!(
+            self.commands.get(self.pos),
+            None,
+            "unexpected command #{}",
+            self.pos
+        );
+    }
+}
+
+fn range(start: u16, end: u16) -> [u8; 4] {
+    let [s0, s1] = start.to_be_bytes();
+    let [e0, e1] = end.to_be_bytes();
+    [s0, s1, e0, e1]
+}
+
+/// [`DelayNs`] that doesn't wait, only adds up the requested time.
+#[derive(Default)]
+pub struct MockDelay {
+    pub total_ns: u64,
+}
+
+impl DelayNs for MockDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.total_ns += ns as u64;
+    }
+}
+
+/// Output pin that ignores every write.
+pub struct NoopPin;
+
+impl embedded_hal::digital::ErrorType for NoopPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoopPin {
+    fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Input pin that reads back a scripted sequence of levels, repeating the
+/// last one once it runs out.
+pub struct MockInputPin {
+    levels: Vec<bool>,
+    pub reads: usize,
+}
+
+impl MockInputPin {
+    pub fn new(levels: &[bool]) -> Self {
+        Self {
+            levels: levels.to_vec(),
+            reads: 0,
+        }
+    }
+
+    fn next(&mut self) -> bool {
+        let level = self.levels[self.reads.min(self.levels.len() - 1)];
+        self.reads += 1;
+        level
+    }
+}
+
+impl embedded_hal::digital::ErrorType for MockInputPin {
+    type Error = Infallible;
+}
+
+impl InputPin for MockInputPin {
+    fn is_high(&mut self) -> core::result::Result<bool, Infallible> {
+        Ok(self.next())
+    }
+
+    fn is_low(&mut self) -> core::result::Result<bool, Infallible> {
+        Ok(!self.next())
+    }
+}
+
+/// PIO TX FIFO that records every word. The words pass through the FIFO at
+/// once but only leave the OSR one per poll of the stall flag, which is
+/// sticky like the real one.
+#[derive(Default)]
+pub struct MockTx {
+    pub words: Vec<u32>,
+    /// A stopped state machine never stalls.
+    pub stopped: bool,
+    pending: Rc<Cell<usize>>,
+    stalled: Cell<bool>,
+}
+
+impl MockTx {
+    /// DC pin that checks the state machine has run dry on every change.
+    pub fn dc_pin(&self) -> BusIdlePin {
+        BusIdlePin {
+            pending: self.pending.clone(),
+            levels: Vec::new(),
+        }
+    }
+}
+
+impl PioTx for MockTx {
+    fn write(&mut self, value: u32) -> bool {
+        self.words.push(value);
+        self.pending.set(self.pending.get() + 1);
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+
+    fn has_stalled(&self) -> bool {
+        if self.stopped {
+            return self.stalled.get();
+        }
+        match self.pending.get() {
+            0 => self.stalled.set(true),
+            n => self.pending.set(n - 1),
+        }
+        self.stalled.get()
+    }
+
+    fn clear_stalled_flag(&mut self) {
+        self.stalled.set(false);
+    }
+}
+
+/// Output pin of a [`MockTx`] bus, panics if it is set while words are
+/// still being shifted out.
+pub struct BusIdlePin {
+    pending: Rc<Cell<usize>>,
+    pub levels: Vec<bool>,
+}
+
+impl BusIdlePin {
+    fn set(&mut self, level: bool) {
+        assert_eq!(self.pending.get(), 0, "DC changed mid-transfer");
+        self.levels.push(level);
+    }
+}
+
+impl embedded_hal::digital::ErrorType for BusIdlePin {
+    type Error = Infallible;
+}
+
+impl OutputPin for BusIdlePin {
+    fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+        self.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+        self.set(true);
+        Ok(())
+    }
+}
+
+/// I2C bus that records what is written and answers reads with scripted
+/// bytes, in order.
+#[derive(Default)]
+pub struct MockI2c {
+    /// Address and bytes of every write.
+    pub writes: Vec<(u8, Vec<u8>)>,
+    responses: VecDeque<Vec<u8>>,
+    /// Fails every transaction with this error.
+    pub fail: Option<i2c::ErrorKind>,
+}
+
+impl MockI2c {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the bytes the next read returns, which has to be as long.
+    pub fn respond(&mut self, bytes: &[u8]) -> &mut Self {
+        self.responses.push_back(bytes.to_vec());
+        self
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c for MockI2c {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> core::result::Result<(), i2c::ErrorKind> {
+        if let Some(e) = self.fail {
+            return Err(e);
+        }
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => self.writes.push((address, bytes.to_vec())),
+                Operation::Read(buf) => {
+                    let response = self.responses.pop_front().expect("unexpected read");
+                    buf.copy_from_slice(&response);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Touch controller playing back scripted samples.
+pub struct MockTouch {
+    samples: VecDeque<Option<(u16, u16)>>,
+}
+
+impl MockTouch {
+    pub fn new(samples: impl IntoIterator<Item = Option<(u16, u16)>>) -> Self {
+        Self {
+            samples: samples.into_iter().collect(),
+        }
+    }
+
+    /// `count` reads of the same sample, then released for `count` reads.
+    pub fn tap(raw: (u16, u16), count: usize) -> impl Iterator<Item = Option<(u16, u16)>> {
+        core::iter::repeat_n(Some(raw), count).chain(core::iter::repeat_n(None, count))
+    }
+}
+
+impl RawTouch for MockTouch {
+    type Error = Infallible;
+
+    fn read_raw(&mut self) -> core::result::Result<Option<(u16, u16)>, Infallible> {
+        Ok(self.samples.pop_front().expect("touch script ran out"))
+    }
+}
+
+/// Discards defmt output, so code that logs can run on the host.
+#[defmt::global_logger]
+struct NoopLogger;
+
+unsafe impl defmt::Logger for NoopLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u8}", 0);
@@ -0,0 +1,183 @@
+use embedded_graphics::prelude::{Point, Size};
+
+/// MADCTL (0x36) bits.
+const MY: u8 = 0x80;
+const MX: u8 = 0x40;
+const MV: u8 = 0x20;
+const BGR: u8 = 0x08;
+
+/// Display orientation, set with `ILI9488::set_orientation`.
+///
+/// The flag of each variant mirrors the image horizontally. `Landscape` is
+/// the default; the image is turned counter-clockwise for `Portrait`, by
+/// 180 degrees for `LandscapeFlipped` and clockwise for `PortraitFlipped`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait(bool),
+    Landscape(bool),
+    PortraitFlipped(bool),
+    LandscapeFlipped(bool),
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::Landscape(false)
+    }
+}
+
+impl Orientation {
+    pub fn is_landscape(self) -> bool {
+        matches!(
+            self,
+            Orientation::Landscape(_) | Orientation::LandscapeFlipped(_)
+        )
+    }
+
+    pub fn is_mirrored(self) -> bool {
+        match self {
+            Orientation::Portrait(m)
+            | Orientation::Landscape(m)
+            | Orientation::PortraitFlipped(m)
+            | Orientation::LandscapeFlipped(m) => m,
+        }
+    }
+
+    /// Panel position of the logical point `point`. The panel is addressed
+    /// as in `Landscape(false)`, `panel` being its size there.
+    pub fn to_panel(self, point: Point, panel: Size) -> Point {
+        let (w, h) = (panel.width as i32 - 1, panel.height as i32 - 1);
+        // Mirroring flips x before the rotation, across the logical width.
+        let width = if self.is_landscape() { w } else { h };
+        let Point { x, y } = point;
+        let x = if self.is_mirrored() { width - x } else { x };
+        match self {
+            Orientation::Landscape(_) => Point::new(x, y),
+            Orientation::Portrait(_) => Point::new(y, h - x),
+            Orientation::LandscapeFlipped(_) => Point::new(w - x, h - y),
+            Orientation::PortraitFlipped(_) => Point::new(w - y, x),
+        }
+    }
+
+    /// Logical point at panel position `point`, the inverse of `to_panel`.
+    pub fn from_panel(self, point: Point, panel: Size) -> Point {
+        let (w, h) = (panel.width as i32 - 1, panel.height as i32 - 1);
+        let (x, y) = match self {
+            Orientation::Landscape(_) => (point.x, point.y),
+            Orientation::Portrait(_) => (h - point.y, point.x),
+            Orientation::LandscapeFlipped(_) => (w - point.x, h - point.y),
+            Orientation::PortraitFlipped(_) => (point.y, w - point.x),
+        };
+        let width = if self.is_landscape() { w } else { h };
+        let x = if self.is_mirrored() { width - x } else { x };
+        Point::new(x, y)
+    }
+
+    /// MADCTL value for this orientation, the panel has a BGR filter.
+    pub(crate) fn madctl(self) -> u8 {
+        let rotation = match self {
+            Orientation::Portrait(_) => MX,
+            Orientation::Landscape(_) => MV,
+            Orientation::PortraitFlipped(_) => MY,
+            Orientation::LandscapeFlipped(_) => MY | MX | MV,
+        };
+        // Mirroring flips the column order, which is the x axis in every
+        // orientation.
+        let mirror = if self.is_mirrored() { MX } else { 0 };
+        (rotation ^ mirror) | BGR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Emulator, HEIGHT, WIDTH};
+    use crate::mock::{MockDelay, NoopPin};
+    use crate::ILI9488;
+    use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+    #[test]
+    fn landscape_is_the_init_default() {
+        assert_eq!(Orientation::default().madctl(), 0x28);
+    }
+
+    /// Panel position (in the landscape dump) of logical pixels `(0, 0)`
+    /// and `(1, 0)` in `orientation`.
+    fn origin_and_x_axis(orientation: Orientation) -> [(usize, usize); 2] {
+        let mut display: ILI9488<_, NoopPin, NoopPin> =
+            ILI9488::new(Emulator::new(), None, None, 480, 320);
+        display.init(&mut MockDelay::default()).unwrap();
+        display.set_orientation(orientation).unwrap();
+        display
+            .draw_iter([
+                Pixel(Point::new(0, 0), Rgb565::RED),
+                Pixel(Point::new(1, 0), Rgb565::GREEN),
+            ])
+            .unwrap();
+
+        let emu = display.di_mut();
+        let find = |color: [u8; 3]| {
+            (0..WIDTH * HEIGHT)
+                .map(|i| (i % WIDTH, i / WIDTH))
+                .find(|&(x, y)| emu.pixel(x, y) == color)
+                .unwrap()
+        };
+        [find([0xFF, 0, 0]), find([0, 0xFF, 0])]
+    }
+
+    #[test]
+    fn orientations_rotate_the_image() {
+        use Orientation::*;
+        let (w, h) = (WIDTH - 1, HEIGHT - 1);
+        assert_eq!(origin_and_x_axis(Landscape(false)), [(0, 0), (1, 0)]);
+        assert_eq!(origin_and_x_axis(Portrait(false)), [(0, h), (0, h - 1)]);
+        assert_eq!(
+            origin_and_x_axis(LandscapeFlipped(false)),
+            [(w, h), (w - 1, h)]
+        );
+        assert_eq!(origin_and_x_axis(PortraitFlipped(false)), [(w, 0), (w, 1)]);
+    }
+
+    /// Where `to_panel` puts logical `(0, 0)` and `(1, 0)`.
+    fn mapped_origin_and_x_axis(orientation: Orientation) -> [(usize, usize); 2] {
+        let panel = Size::new(WIDTH as u32, HEIGHT as u32);
+        [Point::new(0, 0), Point::new(1, 0)].map(|p| {
+            let p = orientation.to_panel(p, panel);
+            (p.x as usize, p.y as usize)
+        })
+    }
+
+    #[test]
+    fn panel_mapping_follows_madctl() {
+        use Orientation::*;
+        let panel = Size::new(WIDTH as u32, HEIGHT as u32);
+        for mirrored in [false, true] {
+            let all = [
+                Portrait(mirrored),
+                Landscape(mirrored),
+                PortraitFlipped(mirrored),
+                LandscapeFlipped(mirrored),
+            ];
+            for orientation in all {
+                let expected = origin_and_x_axis(orientation);
+                assert_eq!(mapped_origin_and_x_axis(orientation), expected);
+
+                let p = Point::new(17, 42);
+                let back = orientation.from_panel(orientation.to_panel(p, panel), panel);
+                assert_eq!(back, p, "{orientation:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn mirroring_flips_x() {
+        use Orientation::*;
+        let (w, h) = (WIDTH - 1, HEIGHT - 1);
+        assert_eq!(origin_and_x_axis(Landscape(true)), [(w, 0), (w - 1, 0)]);
+        assert_eq!(origin_and_x_axis(Portrait(true)), [(0, 0), (0, 1)]);
+        assert_eq!(origin_and_x_axis(LandscapeFlipped(true)), [(0, h), (1, h)]);
+        assert_eq!(
+            origin_and_x_axis(PortraitFlipped(true)),
+            [(w, h), (w, h - 1)]
+        );
+    }
+}
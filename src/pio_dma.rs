@@ -0,0 +1,381 @@
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+use fugit::HertzU32;
+use rp2040_hal::dma::{
+    single_buffer::{Config, Transfer},
+    ReadTarget, SingleChannel, WriteTarget,
+};
+use rp2040_hal::pio::{PIOExt, StateMachineIndex, Tx, UninitStateMachine, ValidStateMachine, PIO};
+
+use crate::{
+    pio16_program, pio16_push, pio16_send_repeated, pio16_write_data, pio_flush, pio_flush_timeout,
+    start_bus_sm, BusPixelFormat, BusStateMachine, BusTiming, PioTx, RepeatDataCommand,
+    SetupResult, PIO16_MAX,
+};
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// The PIO TX FIFO seen as a 16-bit DMA sink.
+///
+/// Half-word writes to the FIFO register are replicated into both halves of
+/// the FIFO entry, so a state machine shifting right with a 16-bit pull
+/// threshold gets exactly one pixel per DMA transfer.
+pub struct PioFifo16<TX>(TX);
+
+// Safety: this only writes to the state machine FIFO, so it doesn't
+// interact with rust-managed memory.
+unsafe impl<SM: ValidStateMachine> WriteTarget for PioFifo16<Tx<SM>> {
+    type TransmittedWord = u16;
+
+    fn tx_treq() -> Option<u8> {
+        Some(SM::tx_dreq())
+    }
+
+    fn tx_address_count(&mut self) -> (u32, u32) {
+        (self.0.fifo_address() as u32, u32::MAX)
+    }
+
+    fn tx_increment(&self) -> bool {
+        false
+    }
+}
+
+/// 16-bit 8080 bus which streams pixel buffers to the PIO with DMA.
+///
+/// The state machine must run [`pio16_program`] and be set up like the one
+/// used by [`Pio16BitBus`]: right shift, autopull and a pull threshold of 16.
+/// [`setup`](PioDmaBus::setup) does that.
+///
+/// Commands and small parameter writes go through the FIFO directly, pixel
+/// data from RAM can be sent with [`start_transfer`] while the CPU renders
+/// the next buffer, then reclaimed with [`wait_transfer`].
+///
+/// [`Pio16BitBus`]: crate::Pio16BitBus
+/// [`pio16_program`]: crate::pio16_program
+/// [`start_transfer`]: PioDmaBus::start_transfer
+/// [`wait_transfer`]: PioDmaBus::wait_transfer
+pub struct PioDmaBus<TX, CH, DC, BUF>
+where
+    TX: PioTx,
+    CH: SingleChannel,
+    BUF: ReadTarget<ReceivedWord = u16>,
+    PioFifo16<TX>: WriteTarget<TransmittedWord = u16>,
+{
+    /// The channel and FIFO while no transfer holds them.
+    ch: Option<CH>,
+    fifo: Option<PioFifo16<TX>>,
+    transfer: Option<Transfer<CH, BUF, PioFifo16<TX>>>,
+    done: Option<BUF>,
+    dc: DC,
+}
+
+impl<TX, CH, DC, BUF> PioDmaBus<TX, CH, DC, BUF>
+where
+    TX: PioTx,
+    CH: SingleChannel,
+    DC: OutputPin,
+    BUF: ReadTarget<ReceivedWord = u16>,
+    PioFifo16<TX>: WriteTarget<TransmittedWord = u16>,
+{
+    pub fn new(tx: TX, ch: CH, dc: DC) -> Self {
+        Self {
+            ch: Some(ch),
+            fifo: Some(PioFifo16(tx)),
+            transfer: None,
+            done: None,
+            dc,
+        }
+    }
+
+    /// Returns `true` while a DMA transfer is still running.
+    pub fn is_busy(&self) -> bool {
+        self.transfer.as_ref().is_some_and(|t| !t.is_done())
+    }
+
+    /// Starts streaming `buf` to the panel as pixel data.
+    ///
+    /// The address window and RAMWR must already have been sent. `buf` can
+    /// hold up to 32768 values, the most a [`pio16_program`] transfer takes.
+    /// If a previous transfer is still in flight it is waited for first, and
+    /// its buffer is handed back so it can be refilled. If the transfer can't
+    /// be started `buf` comes back with the error, and the previous buffer
+    /// is kept for [`wait_transfer`](Self::wait_transfer).
+    ///
+    /// [`pio16_program`]: crate::pio16_program
+    pub fn start_transfer(
+        &mut self,
+        buf: BUF,
+    ) -> core::result::Result<Option<BUF>, (DisplayError, BUF)> {
+        let (_, len) = buf.rx_address_count();
+        if len == 0 || len as usize > PIO16_MAX {
+            return Err((DisplayError::OutOfBoundsError, buf));
+        }
+        self.flush();
+        if self.dc.set_high().is_err() {
+            return Err((DisplayError::DCError, buf));
+        }
+
+        let (ch, mut fifo) = match (self.ch.take(), self.fifo.take()) {
+            (Some(ch), Some(fifo)) => (ch, fifo),
+            (ch, fifo) => {
+                (self.ch, self.fifo) = (ch, fifo);
+                return Err((DisplayError::BusWriteError, buf));
+            }
+        };
+        pio16_push(&mut fifo.0, len - 1);
+        self.transfer = Some(Config::new(ch, buf, fifo).start());
+        Ok(self.done.take())
+    }
+
+    /// Blocks until the current transfer has been shifted out to the panel.
+    ///
+    /// Returns the buffer of the last finished transfer, if it was not
+    /// already returned.
+    pub fn wait_transfer(&mut self) -> Option<BUF> {
+        self.flush();
+        self.done.take()
+    }
+
+    /// Stops using the bus and returns the underlying resources.
+    pub fn free(mut self) -> (TX, CH, DC, Option<BUF>) {
+        let buf = self.wait_transfer();
+        let (ch, fifo) = (self.ch.take().unwrap(), self.fifo.take().unwrap());
+        (fifo.0, ch, self.dc, buf)
+    }
+
+    /// Waits until the transfer in flight, if any, and then the state
+    /// machine have shifted out everything queued so far, so DC can be
+    /// toggled without cutting off the last values. The FIFO running empty
+    /// isn't enough, the last value may still be in the OSR.
+    ///
+    /// Never returns if the state machine isn't running, see
+    /// [`flush_timeout`](Self::flush_timeout).
+    pub fn flush(&mut self) {
+        self.finish_transfer();
+        if let Some(fifo) = self.fifo.as_mut() {
+            pio_flush(&mut fifo.0);
+        }
+    }
+
+    /// Like [`flush`](Self::flush), polling every microsecond and giving up
+    /// with a `BusWriteError` after `timeout_us`.
+    pub fn flush_timeout(&mut self, delay_source: &mut impl DelayNs, timeout_us: u32) -> Result {
+        let mut left = timeout_us;
+        while self.is_busy() {
+            if left == 0 {
+                return Err(DisplayError::BusWriteError);
+            }
+            left -= 1;
+            delay_source.delay_us(1);
+        }
+        pio_flush_timeout(self.fifo()?, delay_source, left)
+    }
+
+    /// Waits for the DMA transfer in flight, if any, and keeps its buffer
+    /// for [`wait_transfer`](Self::wait_transfer).
+    fn finish_transfer(&mut self) {
+        if let Some(transfer) = self.transfer.take() {
+            let (ch, buf, fifo) = transfer.wait();
+            (self.ch, self.fifo) = (Some(ch), Some(fifo));
+            self.done = Some(buf);
+        }
+    }
+
+    /// The FIFO, once the transfer in flight is done.
+    fn fifo(&mut self) -> Result<&mut TX> {
+        self.finish_transfer();
+        let fifo = self.fifo.as_mut().ok_or(DisplayError::BusWriteError)?;
+        Ok(&mut fifo.0)
+    }
+
+    pub fn write_data(&mut self, data: DataFormat<'_>) -> Result {
+        pio16_write_data(self.fifo()?, data)
+    }
+}
+
+impl<P, SM, CH, DC, BUF> PioDmaBus<Tx<(P, SM)>, CH, DC, BUF>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+    CH: SingleChannel,
+    DC: OutputPin,
+    BUF: ReadTarget<ReceivedWord = u16>,
+{
+    /// Installs [`pio16_program`] on `sm` like [`Pio16BitBus::setup`], with
+    /// `ch` streaming the pixel buffers.
+    ///
+    /// [`pio16_program`]: crate::pio16_program
+    /// [`Pio16BitBus::setup`]: crate::Pio16BitBus::setup
+    #[allow(clippy::too_many_arguments)]
+    pub fn setup(
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        sys_clk: HertzU32,
+        min_write_cycle_ns: u32,
+        wr: u8,
+        d0: u8,
+        ch: CH,
+        dc: DC,
+    ) -> SetupResult<(Self, BusStateMachine<P, SM>)> {
+        let timing = BusTiming::new(sys_clk, min_write_cycle_ns)?;
+        let program = pio16_program(&timing);
+        let (sm, tx) = start_bus_sm(pio, sm, &program, &timing, wr, d0, 16)?;
+        Ok((Self::new(tx, ch, dc), sm))
+    }
+}
+
+impl<TX, CH, DC, BUF> BusPixelFormat for PioDmaBus<TX, CH, DC, BUF>
+where
+    TX: PioTx,
+    CH: SingleChannel,
+    DC: OutputPin,
+    BUF: ReadTarget<ReceivedWord = u16>,
+    PioFifo16<TX>: WriteTarget<TransmittedWord = u16>,
+{
+    const THREE_BYTE_PIXELS: bool = false;
+}
+
+impl<TX, CH, DC, BUF> WriteOnlyDataCommand for PioDmaBus<TX, CH, DC, BUF>
+where
+    TX: PioTx,
+    CH: SingleChannel,
+    DC: OutputPin,
+    BUF: ReadTarget<ReceivedWord = u16>,
+    PioFifo16<TX>: WriteTarget<TransmittedWord = u16>,
+{
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        // The DC line must not change while pixels are still in flight.
+        self.flush();
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.write_data(cmd)?;
+        Ok(())
+    }
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.flush();
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.write_data(buf)?;
+        Ok(())
+    }
+}
+
+/// Single-value pixels are repeated by the state machine, like on
+/// [`Pio16BitBus`](crate::Pio16BitBus).
+impl<TX, CH, DC, BUF> RepeatDataCommand for PioDmaBus<TX, CH, DC, BUF>
+where
+    TX: PioTx,
+    CH: SingleChannel,
+    DC: OutputPin,
+    BUF: ReadTarget<ReceivedWord = u16>,
+    PioFifo16<TX>: WriteTarget<TransmittedWord = u16>,
+{
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result {
+        self.flush();
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        pio16_send_repeated(self.fifo()?, pixel, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{BusIdlePin, MockDelay, MockTx};
+    use crate::tests::run_pio16;
+    use crate::PIO16_REPEAT;
+    use rp2040_hal::dma::{Channel, CH0};
+
+    // Safety: the host tests never start a transfer.
+    unsafe impl WriteTarget for PioFifo16<MockTx> {
+        type TransmittedWord = u16;
+
+        fn tx_treq() -> Option<u8> {
+            None
+        }
+
+        fn tx_address_count(&mut self) -> (u32, u32) {
+            (0, u32::MAX)
+        }
+
+        fn tx_increment(&self) -> bool {
+            false
+        }
+    }
+
+    type Bus = PioDmaBus<MockTx, Channel<CH0>, BusIdlePin, &'static [u16]>;
+
+    /// A bus without a DMA channel, which only the FIFO path can use.
+    fn bus() -> Bus {
+        let tx = MockTx::default();
+        PioDmaBus {
+            ch: None,
+            dc: tx.dc_pin(),
+            fifo: Some(PioFifo16(tx)),
+            transfer: None,
+            done: None,
+        }
+    }
+
+    #[test]
+    fn dc_waits_for_the_last_value() {
+        let mut bus = bus();
+        bus.send_commands(DataFormat::U8(&[0x2A])).unwrap();
+        bus.send_data(DataFormat::U8(&[0, 0, 1, 0xDF])).unwrap();
+        bus.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        bus.send_data(DataFormat::U16(&[0xF800, 0x07E0])).unwrap();
+        // Every DC change was checked against the values still queued.
+        assert_eq!(bus.dc.levels, [false, true, false, true]);
+        let words = &bus.fifo.as_ref().unwrap().0.words;
+        let values = [0x2A, 0, 0, 1, 0xDF, 0x2C, 0xF800, 0x07E0];
+        assert_eq!(run_pio16(words), values);
+    }
+
+    #[test]
+    fn failed_transfer_returns_the_buffer() {
+        let mut bus = bus();
+        bus.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        let pixels: &'static [u16] = &[0xFFFF; 4];
+        let (error, buf) = bus.start_transfer(pixels).unwrap_err();
+        assert!(matches!(error, DisplayError::BusWriteError));
+        assert_eq!(buf, pixels);
+        assert_eq!(bus.dc.levels, [false, true]);
+        // The FIFO stays with the bus.
+        assert!(bus.fifo.is_some());
+        bus.send_data(DataFormat::U16(&[0xF800])).unwrap();
+        let words = &bus.fifo.as_ref().unwrap().0.words;
+        assert_eq!(run_pio16(words), [0x2C, 0xF800]);
+
+        // Longer than a pio16_program transfer, turned down up front.
+        let long: &'static [u16] = &[0; PIO16_MAX + 1];
+        let (error, buf) = bus.start_transfer(long).unwrap_err();
+        assert!(matches!(error, DisplayError::OutOfBoundsError));
+        assert_eq!(buf.len(), PIO16_MAX + 1);
+    }
+
+    #[test]
+    fn flush_gives_up_on_a_stopped_state_machine() {
+        let mut bus = bus();
+        bus.send_data(DataFormat::U16(&[1, 2, 3])).unwrap();
+        let mut delay = MockDelay::default();
+        bus.flush_timeout(&mut delay, 100).unwrap();
+        // The header and three values leave the mock one per poll.
+        assert_eq!(delay.total_ns, 4_000);
+
+        bus.fifo.as_mut().unwrap().0.stopped = true;
+        let mut delay = MockDelay::default();
+        let error = bus.flush_timeout(&mut delay, 100).unwrap_err();
+        assert!(matches!(error, DisplayError::BusWriteError));
+        assert_eq!(delay.total_ns, 100_000);
+    }
+
+    #[test]
+    fn repeats_without_dma() {
+        let mut bus = bus();
+        bus.send_commands(DataFormat::U8(&[0x2C])).unwrap();
+        bus.send_repeated(DataFormat::U16BE(&mut [0x07E0]), 1000)
+            .unwrap();
+        let words = &bus.fifo.as_ref().unwrap().0.words;
+        assert_eq!(words[2..], [PIO16_REPEAT | 999, 0x07E0]);
+        let mut expected = vec![0x2C];
+        expected.extend([0x07E0; 1000]);
+        assert_eq!(run_pio16(words), expected);
+    }
+}
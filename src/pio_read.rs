@@ -0,0 +1,146 @@
+use core::ops::Range;
+
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::digital::OutputPin;
+use rp2040_hal::pio::{PinDir, Running, StateMachine, ValidStateMachine};
+use rp2040_hal::sio::Sio;
+
+use crate::{
+    BusPixelFormat, Pio8BitBus, PioTx, PixelFormat, Read8BitDataCommand, ReadDataCommand,
+    RepeatDataCommand,
+};
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// 8080 bus with an RD strobe, built on top of one of the PIO write buses.
+///
+/// Writes go through the wrapped bus unchanged. For reads the data pins of
+/// the state machine are switched to inputs, RD is toggled from the CPU and
+/// the bus is sampled through SIO, then the pins are handed back to the PIO.
+///
+/// Register reads always come back on `D[7:0]`, so `data_pins` only needs to
+/// cover the low eight data lines, also on a 16-bit wide bus. GRAM reads
+/// don't, so `read_pixels` only takes a [`Pio8BitBus`] underneath.
+pub struct PioReadBus<B, SM: ValidStateMachine, RD> {
+    bus: B,
+    sm: Option<StateMachine<SM, Running>>,
+    rd: RD,
+    data_pins: Range<u8>,
+    strobe_cycles: u32,
+}
+
+impl<B, SM, RD> PioReadBus<B, SM, RD>
+where
+    B: WriteOnlyDataCommand,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+    /// `d0` is the first data pin of the bus, `strobe_cycles` is the number
+    /// of system clock cycles RD is held low and high for each byte. The
+    /// ILI9488 wants at least 160ns + 90ns for GRAM reads.
+    pub fn new(
+        bus: B,
+        sm: StateMachine<SM, Running>,
+        mut rd: RD,
+        d0: u8,
+        strobe_cycles: u32,
+    ) -> Result<Self> {
+        rd.set_high().map_err(|_| DisplayError::BusWriteError)?;
+        Ok(Self {
+            bus,
+            sm: Some(sm),
+            rd,
+            data_pins: d0..d0 + 8,
+            strobe_cycles,
+        })
+    }
+
+    pub fn free(self) -> (B, StateMachine<SM, Running>, RD) {
+        (self.bus, self.sm.unwrap(), self.rd)
+    }
+
+    /// Pin directions can only be changed while the state machine is
+    /// stopped. It is idle at this point, so it resumes where it left off.
+    fn set_data_dir(&mut self, dir: PinDir) {
+        if let Some(sm) = self.sm.take() {
+            let mut sm = sm.stop();
+            sm.set_pindirs(self.data_pins.clone().map(|pin| (pin, dir)));
+            self.sm = Some(sm.start());
+        }
+    }
+
+    fn strobe(&mut self) -> Result<u8> {
+        self.rd.set_low().map_err(|_| DisplayError::BusWriteError)?;
+        cortex_m::asm::delay(self.strobe_cycles);
+        let value = (Sio::read_bank0() >> self.data_pins.start) as u8;
+        self.rd
+            .set_high()
+            .map_err(|_| DisplayError::BusWriteError)?;
+        cortex_m::asm::delay(self.strobe_cycles);
+        Ok(value)
+    }
+}
+
+impl<B, SM, RD> WriteOnlyDataCommand for PioReadBus<B, SM, RD>
+where
+    B: WriteOnlyDataCommand,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        self.bus.send_commands(cmd)
+    }
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.bus.send_data(buf)
+    }
+}
+
+impl<B, SM, RD> BusPixelFormat for PioReadBus<B, SM, RD>
+where
+    B: BusPixelFormat,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+    const PIXEL_FORMAT: Option<PixelFormat> = B::PIXEL_FORMAT;
+    const THREE_BYTE_PIXELS: bool = B::THREE_BYTE_PIXELS;
+}
+
+impl<B, SM, RD> RepeatDataCommand for PioReadBus<B, SM, RD>
+where
+    B: RepeatDataCommand,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+    fn send_repeated(&mut self, pixel: DataFormat<'_>, count: u32) -> Result {
+        self.bus.send_repeated(pixel, count)
+    }
+}
+
+impl<B, SM, RD> ReadDataCommand for PioReadBus<B, SM, RD>
+where
+    B: WriteOnlyDataCommand,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+    fn read_data(&mut self, buf: &mut [u8]) -> Result {
+        // An empty data phase waits for the PIO to drain and raises DC.
+        self.bus.send_data(DataFormat::U8(&[]))?;
+
+        self.set_data_dir(PinDir::Input);
+        let res = buf.iter_mut().try_for_each(|byte| {
+            *byte = self.strobe()?;
+            Ok(())
+        });
+        self.set_data_dir(PinDir::Output);
+        res
+    }
+}
+
+impl<TX, DC, SM, RD> Read8BitDataCommand for PioReadBus<Pio8BitBus<TX, DC>, SM, RD>
+where
+    TX: PioTx,
+    DC: OutputPin,
+    SM: ValidStateMachine,
+    RD: OutputPin,
+{
+}
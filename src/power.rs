@@ -0,0 +1,266 @@
+use display_interface::{DisplayError, WriteOnlyDataCommand};
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+
+use crate::{ColorFormat, ILI9488};
+
+type Result<T = ()> = core::result::Result<T, PowerError>;
+
+/// Power modes of the panel, as far as the driver has sent them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerState {
+    /// Sleep In, the panel is blank and the oscillator stopped.
+    pub asleep: bool,
+    pub display_on: bool,
+    /// Idle mode, 8 colours at lower power.
+    pub idle: bool,
+    /// Only the partial area is refreshed, the rest is blank.
+    pub partial: bool,
+}
+
+impl PowerState {
+    /// State after a reset.
+    pub(crate) const RESET: Self = Self {
+        asleep: true,
+        display_on: false,
+        idle: false,
+        partial: false,
+    };
+
+    /// Follows the power commands among everything sent, so modes set with
+    /// `write_reg` are tracked as well.
+    pub(crate) fn track(&mut self, cmd: u8) {
+        match cmd {
+            0x01 => *self = Self::RESET,
+            0x10 => self.asleep = true,
+            0x11 => self.asleep = false,
+            0x12 => self.partial = true,
+            0x13 => self.partial = false,
+            0x28 => self.display_on = false,
+            0x29 => self.display_on = true,
+            0x38 => self.idle = false,
+            0x39 => self.idle = true,
+            _ => {}
+        }
+    }
+}
+
+/// Errors from the power mode methods.
+#[derive(Debug)]
+pub enum PowerError {
+    Display(DisplayError),
+    /// The panel already is in the mode the command switches to, e.g.
+    /// Sleep Out while awake.
+    AlreadyInMode,
+}
+
+impl From<DisplayError> for PowerError {
+    fn from(e: DisplayError) -> Self {
+        PowerError::Display(e)
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    pub fn power_state(&self) -> PowerState {
+        self.power
+    }
+
+    /// Sends `cmd` unless `already` says the panel is in its mode.
+    fn switch_mode(&mut self, cmd: u8, already: bool) -> Result {
+        if already {
+            return Err(PowerError::AlreadyInMode);
+        }
+        self.write_reg(&[cmd])?;
+        Ok(())
+    }
+
+    /// Sleep In (0x10), waiting 120 ms: the controller needs 5 ms before
+    /// the next command, but Sleep Out may only follow 120 ms later.
+    pub fn sleep_in(&mut self, delay_source: &mut impl DelayNs) -> Result {
+        self.switch_mode(0x10, self.power.asleep)?;
+        delay_source.delay_ms(120);
+        Ok(())
+    }
+
+    /// Sleep Out (0x11), waiting the 120 ms the supply and oscillator take
+    /// to settle, which is also the least time before the next Sleep In.
+    pub fn sleep_out(&mut self, delay_source: &mut impl DelayNs) -> Result {
+        self.switch_mode(0x11, !self.power.asleep)?;
+        delay_source.delay_ms(120);
+        Ok(())
+    }
+
+    /// Display On (0x29), shows GRAM again.
+    pub fn display_on(&mut self) -> Result {
+        self.switch_mode(0x29, self.power.display_on)
+    }
+
+    /// Display Off (0x28), blanks the panel, GRAM keeps its content and can
+    /// still be written.
+    pub fn display_off(&mut self) -> Result {
+        self.switch_mode(0x28, !self.power.display_on)
+    }
+
+    /// Idle Mode On (0x39), only the MSB of each channel is shown.
+    pub fn idle_mode_on(&mut self) -> Result {
+        self.switch_mode(0x39, self.power.idle)
+    }
+
+    /// Idle Mode Off (0x38).
+    pub fn idle_mode_off(&mut self) -> Result {
+        self.switch_mode(0x38, !self.power.idle)
+    }
+
+    /// Sets the partial area to panel lines `start..=end` (0x30) and turns
+    /// on Partial Mode (0x12). The area wraps around if `end` is before
+    /// `start`; it can be moved while in partial mode.
+    pub fn partial_mode_on(&mut self, start: u16, end: u16) -> Result {
+        if start >= self.lines() || end >= self.lines() {
+            return Err(DisplayError::OutOfBoundsError.into());
+        }
+        let [sr_hi, sr_lo] = start.to_be_bytes();
+        let [er_hi, er_lo] = end.to_be_bytes();
+        self.write_reg(&[0x30, sr_hi, sr_lo, er_hi, er_lo])?; // Partial Area
+        if !self.power.partial {
+            self.write_reg(&[0x12])?; // Partial Mode ON
+        }
+        Ok(())
+    }
+
+    /// Normal Display Mode On (0x13), leaves partial mode.
+    pub fn normal_mode_on(&mut self) -> Result {
+        self.switch_mode(0x13, !self.power.partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockInterface, NoopPin};
+
+    fn display() -> ILI9488<MockInterface, NoopPin, NoopPin> {
+        let mut display = ILI9488::new(MockInterface::new(), None, None, 480, 320);
+        display.init(&mut MockDelay::default()).unwrap();
+        display.di.clear();
+        display
+    }
+
+    #[test]
+    fn init_leaves_the_panel_awake_and_on() {
+        let display = display();
+        let awake = PowerState {
+            asleep: false,
+            display_on: true,
+            ..PowerState::RESET
+        };
+        assert_eq!(display.power_state(), awake);
+    }
+
+    #[test]
+    fn sleep_waits_out_the_controller() {
+        let mut display = display();
+        let mut delay = MockDelay::default();
+        display.sleep_in(&mut delay).unwrap();
+        assert_eq!(delay.total_ns, 120_000_000);
+        assert!(display.power_state().asleep);
+
+        display.sleep_out(&mut delay).unwrap();
+        assert_eq!(delay.total_ns, 240_000_000);
+        assert!(!display.power_state().asleep);
+        display
+            .di
+            .expect()
+            .command(0x10, &[])
+            .command(0x11, &[])
+            .end();
+    }
+
+    #[test]
+    fn blank_and_wake_in_a_frame() {
+        let mut display = display();
+        display.display_off().unwrap();
+        display.idle_mode_on().unwrap();
+        display.idle_mode_off().unwrap();
+        display.display_on().unwrap();
+        display
+            .di
+            .expect()
+            .command(0x28, &[])
+            .command(0x39, &[])
+            .command(0x38, &[])
+            .command(0x29, &[])
+            .end();
+        assert!(!display.power_state().idle);
+    }
+
+    #[test]
+    fn redundant_switches_are_refused() {
+        let mut display = display();
+        let mut delay = MockDelay::default();
+        let refused = [
+            display.sleep_out(&mut delay),
+            display.display_on(),
+            display.idle_mode_off(),
+            display.normal_mode_on(),
+        ];
+        for result in refused {
+            assert!(matches!(result, Err(PowerError::AlreadyInMode)));
+        }
+        display.sleep_in(&mut delay).unwrap();
+        assert!(matches!(
+            display.sleep_in(&mut delay),
+            Err(PowerError::AlreadyInMode)
+        ));
+        display.di.expect().command(0x10, &[]).end();
+        assert_eq!(delay.total_ns, 120_000_000);
+    }
+
+    #[test]
+    fn partial_area_can_move() {
+        let mut display = display();
+        display.partial_mode_on(0, 99).unwrap();
+        display.partial_mode_on(400, 20).unwrap();
+        assert!(matches!(
+            display.partial_mode_on(0, 480),
+            Err(PowerError::Display(DisplayError::OutOfBoundsError))
+        ));
+        display.normal_mode_on().unwrap();
+        display
+            .di
+            .expect()
+            .command(0x30, &[0, 0, 0, 99])
+            .command(0x12, &[])
+            .command(0x30, &[0x01, 0x90, 0, 20])
+            .command(0x13, &[])
+            .end();
+        assert!(!display.power_state().partial);
+    }
+
+    #[test]
+    fn reset_forgets_the_modes() {
+        let mut display = display();
+        display.idle_mode_on().unwrap();
+        display.write_command(0x01).unwrap();
+        assert_eq!(display.power_state(), PowerState::RESET);
+
+        let mut delay = MockDelay::default();
+        display.init(&mut delay).unwrap();
+        display.idle_mode_on().unwrap();
+        let idle = display.power_state();
+        // Without a RST pin nothing is reset.
+        display.hard_reset(&mut delay);
+        assert_eq!(display.power_state(), idle);
+
+        let mut display: ILI9488<_, _, NoopPin> =
+            ILI9488::new(MockInterface::new(), Some(NoopPin), None, 480, 320);
+        display.init(&mut delay).unwrap();
+        display.idle_mode_on().unwrap();
+        display.hard_reset(&mut delay);
+        assert_eq!(display.power_state(), PowerState::RESET);
+    }
+}
@@ -0,0 +1,232 @@
+use display_interface::{DisplayError, WriteOnlyDataCommand};
+use embedded_hal::digital::OutputPin;
+
+use crate::{ColorFormat, Result, ILI9488};
+
+/// Vertical scrolling area (0x33), in panel lines.
+///
+/// The panel scrolls along its 480 lines, which run along the y axis in the
+/// portrait orientations and along x in landscape. Line 0 is the first line
+/// of GRAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScrollArea {
+    top_fixed: u16,
+    height: u16,
+    bottom_fixed: u16,
+}
+
+impl ScrollArea {
+    /// Area with `height` scrolling lines between the fixed ones, or `None`
+    /// if `height` is 0 or the lines don't fit in a `u16`.
+    pub const fn new(top_fixed: u16, height: u16, bottom_fixed: u16) -> Option<Self> {
+        let lines = top_fixed as u32 + height as u32 + bottom_fixed as u32;
+        if height == 0 || lines > u16::MAX as u32 {
+            return None;
+        }
+        Some(Self {
+            top_fixed,
+            height,
+            bottom_fixed,
+        })
+    }
+
+    /// Whole panel scrolling, the state after reset.
+    pub(crate) const fn full(lines: u16) -> Self {
+        Self {
+            top_fixed: 0,
+            height: lines,
+            bottom_fixed: 0,
+        }
+    }
+
+    /// All lines of the area, fixed and scrolling.
+    const fn lines(&self) -> u16 {
+        self.top_fixed + self.height + self.bottom_fixed
+    }
+
+    /// Lines at the start that stay in place.
+    pub const fn top_fixed(&self) -> u16 {
+        self.top_fixed
+    }
+
+    /// Lines that scroll, wrapping around.
+    pub const fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Lines at the end that stay in place.
+    pub const fn bottom_fixed(&self) -> u16 {
+        self.bottom_fixed
+    }
+
+    /// GRAM line shown at screen line `line` when scrolled by `offset`.
+    pub fn physical_line(&self, line: u16, offset: u16) -> u16 {
+        let scrolled = self.top_fixed..self.top_fixed + self.height;
+        if !scrolled.contains(&line) {
+            return line;
+        }
+        let offset = offset % self.height;
+        self.top_fixed + (line - self.top_fixed + offset) % self.height
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Splits the panel into a fixed top, a scrolling middle and a fixed
+    /// bottom, which have to add up to all 480 lines. The scroll offset goes
+    /// back to 0.
+    pub fn define_scroll_area(
+        &mut self,
+        top_fixed: u16,
+        scroll_height: u16,
+        bottom_fixed: u16,
+    ) -> Result {
+        let area = ScrollArea::new(top_fixed, scroll_height, bottom_fixed)
+            .filter(|area| area.lines() == self.lines())
+            .ok_or(DisplayError::OutOfBoundsError)?;
+
+        let [tfa_hi, tfa_lo] = top_fixed.to_be_bytes();
+        let [vsa_hi, vsa_lo] = scroll_height.to_be_bytes();
+        let [bfa_hi, bfa_lo] = bottom_fixed.to_be_bytes();
+        // Vertical Scrolling Definition
+        self.write_reg(&[0x33, tfa_hi, tfa_lo, vsa_hi, vsa_lo, bfa_hi, bfa_lo])?;
+        self.scroll = area;
+        self.set_scroll_offset(0)
+    }
+
+    pub fn scroll_area(&self) -> ScrollArea {
+        self.scroll
+    }
+
+    /// Scrolls the scroll area by `line` lines (0x37): the GRAM line
+    /// `top_fixed + line` is shown first. Offsets wrap around the height of
+    /// the area.
+    pub fn set_scroll_offset(&mut self, line: u16) -> Result {
+        let line = line % self.scroll.height;
+        let [vsp_hi, vsp_lo] = (self.scroll.top_fixed + line).to_be_bytes();
+        self.write_reg(&[0x37, vsp_hi, vsp_lo])?; // Vertical Scrolling Start Address
+        self.scroll_offset = line;
+        Ok(())
+    }
+
+    pub fn scroll_offset(&self) -> u16 {
+        self.scroll_offset
+    }
+
+    /// GRAM line shown at screen line `line` with the current scroll
+    /// offset, e.g. where to draw the line that scrolled in last.
+    ///
+    /// Both are panel lines as in [`ScrollArea`], not rows of the current
+    /// orientation: the panel line of a point is the x of
+    /// [`Orientation::to_panel`], its y in `Portrait(_)` but its x in
+    /// `Landscape(_)`, counted from the far side when flipped.
+    ///
+    /// [`Orientation::to_panel`]: crate::Orientation::to_panel
+    pub fn physical_row(&self, line: u16) -> u16 {
+        self.scroll.physical_line(line, self.scroll_offset)
+    }
+
+    /// Number of panel lines, the long side in every orientation.
+    pub(crate) fn lines(&self) -> u16 {
+        self.size_x.max(self.size_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockInterface, NoopPin};
+
+    fn display() -> ILI9488<MockInterface, NoopPin, NoopPin> {
+        ILI9488::new(MockInterface::new(), None, None, 480, 320)
+    }
+
+    #[test]
+    fn define_sends_area_and_resets_offset() {
+        let mut display = display();
+        display.define_scroll_area(20, 440, 20).unwrap();
+        display
+            .di
+            .expect()
+            .command(0x33, &[0, 20, 0x01, 0xB8, 0, 20])
+            .command(0x37, &[0, 20])
+            .end();
+        assert_eq!(display.scroll_offset(), 0);
+        assert_eq!(display.scroll_area(), ScrollArea::new(20, 440, 20).unwrap());
+    }
+
+    #[test]
+    fn area_must_cover_the_panel() {
+        let mut display = display();
+        let bad = [(0, 479, 0), (20, 440, 21), (480, 0, 0), (0, 0xFFFF, 1)];
+        for (top, height, bottom) in bad {
+            assert!(matches!(
+                display.define_scroll_area(top, height, bottom),
+                Err(DisplayError::OutOfBoundsError)
+            ));
+        }
+        display.di.expect().end();
+        assert_eq!(display.scroll_area(), ScrollArea::full(480));
+    }
+
+    #[test]
+    fn offset_is_relative_to_the_area_and_wraps() {
+        let mut display = display();
+        display.define_scroll_area(10, 400, 70).unwrap();
+        display.di.clear();
+
+        display.set_scroll_offset(5).unwrap();
+        display.set_scroll_offset(405).unwrap();
+        display.set_scroll_offset(399).unwrap();
+        display
+            .di
+            .expect()
+            .command(0x37, &[0, 15])
+            .command(0x37, &[0, 15])
+            .command(0x37, &[0x01, 0x99])
+            .end();
+        assert_eq!(display.scroll_offset(), 399);
+    }
+
+    #[test]
+    fn whole_panel_scrolls_by_default() {
+        let mut display = display();
+        display.set_scroll_offset(1).unwrap();
+        display.di.expect().command(0x37, &[0, 1]).end();
+        assert_eq!(display.physical_row(479), 0);
+    }
+
+    #[test]
+    fn physical_rows() {
+        let area = ScrollArea::new(10, 400, 70).unwrap();
+        // Fixed lines never move.
+        for line in [0, 9, 410, 479] {
+            assert_eq!(area.physical_line(line, 123), line);
+        }
+        assert_eq!(area.physical_line(10, 0), 10);
+        assert_eq!(area.physical_line(10, 5), 15);
+        assert_eq!(area.physical_line(409, 1), 10);
+        assert_eq!(area.physical_line(409, 400), 409);
+        assert_eq!(area.physical_line(200, 399), 199);
+    }
+
+    #[test]
+    fn new_checks_the_lines() {
+        assert_eq!(ScrollArea::new(480, 0, 0), None);
+        assert_eq!(ScrollArea::new(1, u16::MAX, 0), None);
+        assert_eq!(
+            ScrollArea::new(0, u16::MAX, 0),
+            Some(ScrollArea::full(u16::MAX))
+        );
+        let area = ScrollArea::new(10, 400, 70).unwrap();
+        assert_eq!(
+            (area.top_fixed(), area.height(), area.bottom_fixed()),
+            (10, 400, 70)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+
+use crate::{BusPixelFormat, PixelFormat};
+
+type Result<T = ()> = core::result::Result<T, DisplayError>;
+
+/// Bytes handed to the SPI driver per transaction when streaming.
+const SPI_CHUNK: usize = 3 * 64;
+
+/// 4-wire SPI interface for the ILI9488.
+///
+/// In SPI mode the controller only takes 18-bit pixels, so every 16-bit
+/// value sent as data is taken to be an Rgb565 pixel and goes out as three
+/// RGB666 bytes. Byte data (commands and parameters) is sent unchanged.
+///
+/// `init` selects the matching pixel format on its own.
+pub struct SpiInterface<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+}
+
+/// Expands an Rgb565 value to the RGB666 wire format, each channel in the
+/// upper six bits of its byte.
+fn rgb565_to_666(value: u16) -> [u8; 3] {
+    let r = (value >> 11) as u8 & 0x1F;
+    let g = (value >> 5) as u8 & 0x3F;
+    let b = value as u8 & 0x1F;
+    [r << 3 | r >> 2, g << 2, b << 3 | b >> 2]
+}
+
+impl<SPI, DC> SpiInterface<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    pub fn new(spi: SPI, dc: DC) -> Self {
+        Self { spi, dc }
+    }
+
+    pub fn free(self) -> (SPI, DC) {
+        (self.spi, self.dc)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result {
+        self.spi
+            .write(bytes)
+            .map_err(|_| DisplayError::BusWriteError)
+    }
+
+    fn write_iter(&mut self, iter: impl Iterator<Item = u8>) -> Result {
+        let mut buf = [0; SPI_CHUNK];
+        let mut len = 0;
+        for byte in iter {
+            buf[len] = byte;
+            len += 1;
+            if len == SPI_CHUNK {
+                self.write(&buf)?;
+                len = 0;
+            }
+        }
+        if len > 0 {
+            self.write(&buf[..len])?;
+        }
+        Ok(())
+    }
+
+    fn write_pixels(&mut self, iter: impl Iterator<Item = u16>) -> Result {
+        self.write_iter(iter.flat_map(rgb565_to_666))
+    }
+
+    pub fn write_data(&mut self, data: DataFormat<'_>) -> Result {
+        match data {
+            DataFormat::U8(slice) => self.write(slice),
+            DataFormat::U8Iter(iter) => self.write_iter(iter),
+            DataFormat::U16(slice) => self.write_pixels(slice.iter().copied()),
+            DataFormat::U16BE(slice) => self.write_pixels(slice.iter().copied()),
+            DataFormat::U16LE(slice) => self.write_pixels(slice.iter().copied()),
+            DataFormat::U16BEIter(iter) => self.write_pixels(iter),
+            DataFormat::U16LEIter(iter) => self.write_pixels(iter),
+            _ => Err(DisplayError::DataFormatNotImplemented),
+        }
+    }
+}
+
+impl<SPI, DC> WriteOnlyDataCommand for SpiInterface<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result {
+        self.dc.set_low().map_err(|_| DisplayError::DCError)?;
+        self.write_data(cmd)?;
+        Ok(())
+    }
+    fn send_data(&mut self, buf: DataFormat<'_>) -> Result {
+        self.dc.set_high().map_err(|_| DisplayError::DCError)?;
+        self.write_data(buf)?;
+        Ok(())
+    }
+}
+
+impl<SPI, DC> BusPixelFormat for SpiInterface<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    const PIXEL_FORMAT: Option<PixelFormat> = Some(PixelFormat::Rgb666);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, NoopPin};
+    use core::convert::Infallible;
+    use embedded_hal::spi::{ErrorType, Operation};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// `(dc, bytes)` of every SPI transaction.
+    type Log = Rc<RefCell<Vec<(bool, Vec<u8>)>>>;
+
+    struct MockSpi {
+        log: Log,
+        dc: Rc<RefCell<bool>>,
+    }
+
+    impl ErrorType for MockSpi {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for MockSpi {
+        fn transaction(
+            &mut self,
+            operations: &mut [Operation<'_, u8>],
+        ) -> core::result::Result<(), Infallible> {
+            let mut bytes = Vec::new();
+            for op in operations {
+                if let Operation::Write(buf) = op {
+                    bytes.extend_from_slice(buf);
+                }
+            }
+            self.log.borrow_mut().push((*self.dc.borrow(), bytes));
+            Ok(())
+        }
+    }
+
+    struct MockDc(Rc<RefCell<bool>>);
+
+    impl embedded_hal::digital::ErrorType for MockDc {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for MockDc {
+        fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+            *self.0.borrow_mut() = false;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+            *self.0.borrow_mut() = true;
+            Ok(())
+        }
+    }
+
+    fn interface() -> (SpiInterface<MockSpi, MockDc>, Log) {
+        let log = Log::default();
+        let dc = Rc::new(RefCell::new(false));
+        let spi = MockSpi {
+            log: log.clone(),
+            dc: dc.clone(),
+        };
+        (SpiInterface::new(spi, MockDc(dc)), log)
+    }
+
+    #[test]
+    fn bytes_go_out_unchanged() {
+        let (mut di, log) = interface();
+        di.send_commands(DataFormat::U8(&[0x3A])).unwrap();
+        di.send_data(DataFormat::U8(&[0x66])).unwrap();
+        assert_eq!(*log.borrow(), [(false, vec![0x3A]), (true, vec![0x66])]);
+    }
+
+    #[test]
+    fn pixels_are_packed_as_rgb666() {
+        let (mut di, log) = interface();
+        let mut iter = [0xF800u16, 0x07E0, 0x001F, 0xFFFF, 0x0000].into_iter();
+        di.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [(
+                true,
+                vec![
+                    0xFF, 0x00, 0x00, //
+                    0x00, 0xFC, 0x00, //
+                    0x00, 0x00, 0xFF, //
+                    0xFF, 0xFC, 0xFF, //
+                    0x00, 0x00, 0x00, //
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn long_streams_are_chunked() {
+        let (mut di, log) = interface();
+        let mut iter = core::iter::repeat_n(0xFFFFu16, 100);
+        di.send_data(DataFormat::U16BEIter(&mut iter)).unwrap();
+
+        let log = log.borrow();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].1.len(), SPI_CHUNK);
+        assert_eq!(log[0].1.len() + log[1].1.len(), 300);
+    }
+
+    #[test]
+    fn init_selects_rgb666() {
+        let (di, log) = interface();
+        let mut display: crate::ILI9488<_, NoopPin, NoopPin> =
+            crate::ILI9488::new(di, None, None, 480, 320);
+        display.init(&mut MockDelay::default()).unwrap();
+        let log = log.borrow();
+        let colmod = log.iter().position(|(_, bytes)| bytes == &[0x3A]).unwrap();
+        assert_eq!(log[colmod + 1], (true, vec![0x66]));
+    }
+}
@@ -0,0 +1,240 @@
+use core::marker::PhantomData;
+
+use display_interface::{DisplayError, WriteOnlyDataCommand};
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{InputPin, OutputPin},
+};
+
+use crate::{ColorFormat, Result, ILI9488};
+
+/// Tearing effect output mode, set with `ILI9488::set_tearing_effect`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TearingEffect {
+    /// TE stays low (0x34), the state after reset.
+    Off,
+    /// TE is high during vertical blanking (0x35, 0x00).
+    VBlank,
+    /// TE is high during vertical and horizontal blanking (0x35, 0x01).
+    VAndHBlank,
+}
+
+/// Errors from `ILI9488::wait_for_vsync_timeout`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VsyncError<E> {
+    Pin(E),
+    /// No rising edge within the timeout, TE is off or stuck.
+    Timeout,
+}
+
+impl<DI, RST, BL, C> ILI9488<DI, RST, BL, C>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    /// Adds the pin wired to the TE output, for `wait_for_vsync`.
+    pub fn with_te_pin<TE: InputPin>(self, te: TE) -> ILI9488<DI, RST, BL, C, TE> {
+        ILI9488 {
+            di: self.di,
+            rst: self.rst,
+            bl: self.bl,
+            pixel_format: self.pixel_format,
+            orientation: self.orientation,
+            size_x: self.size_x,
+            size_y: self.size_y,
+            columns: self.columns,
+            pages: self.pages,
+            scroll: self.scroll,
+            scroll_offset: self.scroll_offset,
+            power: self.power,
+            te,
+            color: PhantomData,
+        }
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    pub fn set_tearing_effect(&mut self, mode: TearingEffect) -> Result {
+        match mode {
+            TearingEffect::Off => self.write_reg(&[0x34]), // Tearing Effect Line OFF
+            TearingEffect::VBlank => self.write_reg(&[0x35, 0x00]), // Tearing Effect Line ON
+            TearingEffect::VAndHBlank => self.write_reg(&[0x35, 0x01]),
+        }
+    }
+
+    /// Raises TE when the panel starts refreshing `line` (0x44) instead of
+    /// at the start of vertical blanking, in [`TearingEffect::VBlank`]
+    /// mode. Line 0 is the first line of GRAM, as in [`ScrollArea`].
+    ///
+    /// [`ScrollArea`]: crate::ScrollArea
+    pub fn set_tear_scanline(&mut self, line: u16) -> Result {
+        if line >= self.lines() {
+            return Err(DisplayError::OutOfBoundsError);
+        }
+        let [sts_hi, sts_lo] = line.to_be_bytes();
+        self.write_reg(&[0x44, sts_hi, sts_lo])?; // Set Tear Scanline
+        Ok(())
+    }
+
+    /// The TE pin, e.g. to enable an edge interrupt on it and draw from the
+    /// interrupt handler instead of waiting in `wait_for_vsync`.
+    pub fn te_pin_mut(&mut self) -> &mut TE {
+        &mut self.te
+    }
+}
+
+impl<DI, RST, BL, C, TE> ILI9488<DI, RST, BL, C, TE>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+    TE: InputPin,
+{
+    /// Busy-waits for the rising edge of TE, the start of vertical blanking
+    /// or the tear scanline. Drawing a frame right after it keeps the write
+    /// ahead of the refresh, so the panel never shows half of it.
+    ///
+    /// TE has to be turned on with `set_tearing_effect` first, otherwise
+    /// this never returns, see `wait_for_vsync_timeout`.
+    pub fn wait_for_vsync(&mut self) -> core::result::Result<(), TE::Error> {
+        // Let a pulse that is already running pass, we'd be late for it.
+        while self.te.is_high()? {}
+        while self.te.is_low()? {}
+        Ok(())
+    }
+
+    /// Like `wait_for_vsync`, polling TE every microsecond and giving up
+    /// after `timeout_us`. A frame takes about 17 ms at the default frame
+    /// rate.
+    pub fn wait_for_vsync_timeout(
+        &mut self,
+        delay_source: &mut impl DelayNs,
+        timeout_us: u32,
+    ) -> core::result::Result<(), VsyncError<TE::Error>> {
+        let mut left = timeout_us;
+        for level in [true, false] {
+            while self.te.is_high().map_err(VsyncError::Pin)? == level {
+                if left == 0 {
+                    return Err(VsyncError::Timeout);
+                }
+                left -= 1;
+                delay_source.delay_us(1);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDelay, MockInputPin, MockInterface, NoopPin};
+    use embedded_hal::digital::{ErrorKind, ErrorType};
+
+    fn display() -> ILI9488<MockInterface, NoopPin, NoopPin> {
+        ILI9488::new(MockInterface::new(), None, None, 480, 320)
+    }
+
+    #[test]
+    fn tearing_effect_modes() {
+        use TearingEffect::*;
+        let mut display = display();
+        for mode in [VBlank, VAndHBlank, Off] {
+            display.set_tearing_effect(mode).unwrap();
+        }
+        display
+            .di
+            .expect()
+            .command(0x35, &[0x00])
+            .command(0x35, &[0x01])
+            .command(0x34, &[])
+            .end();
+    }
+
+    #[test]
+    fn tear_scanline_is_a_panel_line() {
+        let mut display = display();
+        display.set_tear_scanline(300).unwrap();
+        display.set_tear_scanline(479).unwrap();
+        assert!(matches!(
+            display.set_tear_scanline(480),
+            Err(DisplayError::OutOfBoundsError)
+        ));
+        display
+            .di
+            .expect()
+            .command(0x44, &[0x01, 0x2C])
+            .command(0x44, &[0x01, 0xDF])
+            .end();
+    }
+
+    #[test]
+    fn waits_for_the_next_rising_edge() {
+        let levels = [true, true, false, false, false, true, true];
+        let mut high = display().with_te_pin(MockInputPin::new(&levels));
+        high.wait_for_vsync().unwrap();
+        assert_eq!(high.te_pin_mut().reads, 6);
+
+        let mut low = display().with_te_pin(MockInputPin::new(&[false, false, true]));
+        low.wait_for_vsync().unwrap();
+        assert_eq!(low.te_pin_mut().reads, 3);
+    }
+
+    #[test]
+    fn vsync_wait_can_time_out() {
+        let mut delay = MockDelay::default();
+        let levels = [true, false, false, false, true];
+        let mut pulsing = display().with_te_pin(MockInputPin::new(&levels));
+        pulsing.wait_for_vsync_timeout(&mut delay, 1000).unwrap();
+        assert_eq!(delay.total_ns, 3_000);
+
+        let mut stuck = display().with_te_pin(MockInputPin::new(&[false]));
+        let mut delay = MockDelay::default();
+        let result = stuck.wait_for_vsync_timeout(&mut delay, 1000);
+        assert_eq!(result, Err(VsyncError::Timeout));
+        assert_eq!(delay.total_ns, 1_000_000);
+    }
+
+    struct BrokenPin;
+
+    impl ErrorType for BrokenPin {
+        type Error = ErrorKind;
+    }
+
+    impl InputPin for BrokenPin {
+        fn is_high(&mut self) -> core::result::Result<bool, ErrorKind> {
+            Err(ErrorKind::Other)
+        }
+
+        fn is_low(&mut self) -> core::result::Result<bool, ErrorKind> {
+            Err(ErrorKind::Other)
+        }
+    }
+
+    #[test]
+    fn te_pin_errors_are_returned() {
+        let mut broken = display().with_te_pin(BrokenPin);
+        assert_eq!(broken.wait_for_vsync(), Err(ErrorKind::Other));
+        let result = broken.wait_for_vsync_timeout(&mut MockDelay::default(), 10);
+        assert_eq!(result, Err(VsyncError::Pin(ErrorKind::Other)));
+    }
+
+    #[test]
+    fn te_pin_keeps_the_display_state() {
+        let mut display = display();
+        display.define_scroll_area(0, 400, 80).unwrap();
+        display.set_scroll_offset(7).unwrap();
+        let display = display.with_te_pin(MockInputPin::new(&[true]));
+        assert_eq!(display.scroll_area().height(), 400);
+        assert_eq!(display.scroll_offset(), 7);
+    }
+}
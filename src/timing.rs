@@ -0,0 +1,192 @@
+use fugit::HertzU32;
+use rp2040_hal::pio::InstallError;
+
+/// Minimum write cycle of the ILI9488 8080 interface, in ns.
+pub const ILI9488_T_WC_NS: u32 = 30;
+/// Minimum WR low time, in ns.
+pub const ILI9488_T_WRL_NS: u32 = 15;
+/// Minimum WR high time, in ns.
+pub const ILI9488_T_WRH_NS: u32 = 15;
+
+/// Longest WR phase a bus program instruction can take: one cycle plus a
+/// 4-bit delay, the fifth delay bit is used for the side-set.
+const MAX_PHASE: u32 = 16;
+
+const PS_PER_S: u64 = 1_000_000_000_000;
+
+/// Clock divisor and WR strobe shape of the PIO write buses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusTiming {
+    /// Integer PIO clock divisor. Fractional divisors are not used, they
+    /// make single PIO cycles shorter than the average.
+    pub divisor: u16,
+    /// PIO cycles WR is held low per write.
+    pub wr_low: u8,
+    /// PIO cycles WR is held high per write.
+    pub wr_high: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingError {
+    /// The write cycle is shorter than the panel allows.
+    WriteCycleTooShort { write_cycle_ns: u32, min_ns: u32 },
+    /// A WR phase is shorter than the panel allows.
+    StrobeTooShort,
+    /// No divisor can stretch the strobe enough at this system clock.
+    ClockTooFast,
+}
+
+/// Errors from the `setup` constructors of the PIO buses.
+#[derive(Debug)]
+pub enum SetupError {
+    Timing(TimingError),
+    Install(InstallError),
+}
+
+impl From<TimingError> for SetupError {
+    fn from(e: TimingError) -> Self {
+        SetupError::Timing(e)
+    }
+}
+
+impl From<InstallError> for SetupError {
+    fn from(e: InstallError) -> Self {
+        SetupError::Install(e)
+    }
+}
+
+/// Length of one PIO cycle, rounded down so cycle counts derived from it
+/// stay on the safe side.
+fn period_ps(sys_clk: HertzU32, divisor: u16) -> u64 {
+    divisor as u64 * PS_PER_S / sys_clk.to_Hz() as u64
+}
+
+fn cycles(ns: u32, period_ps: u64) -> u32 {
+    (ns as u64 * 1000).div_ceil(period_ps) as u32
+}
+
+impl BusTiming {
+    /// Finds the finest timing at `sys_clk` with a write cycle of at least
+    /// `min_write_cycle_ns`, which must not be below [`ILI9488_T_WC_NS`].
+    pub fn new(sys_clk: HertzU32, min_write_cycle_ns: u32) -> Result<Self, TimingError> {
+        if min_write_cycle_ns < ILI9488_T_WC_NS {
+            return Err(TimingError::WriteCycleTooShort {
+                write_cycle_ns: min_write_cycle_ns,
+                min_ns: ILI9488_T_WC_NS,
+            });
+        }
+        if sys_clk.to_Hz() == 0 {
+            return Err(TimingError::ClockTooFast);
+        }
+
+        for divisor in 1..=u16::MAX {
+            let period = period_ps(sys_clk, divisor);
+            if period == 0 {
+                continue;
+            }
+            let mut low = cycles(ILI9488_T_WRL_NS, period).max(1);
+            let mut high = cycles(ILI9488_T_WRH_NS, period).max(1);
+            let total = cycles(min_write_cycle_ns, period);
+            if low + high < total {
+                // Stretch both phases evenly, the high phase gets the odd cycle.
+                let extra = total - low - high;
+                low += extra / 2;
+                high += extra - extra / 2;
+            }
+            if low <= MAX_PHASE && high <= MAX_PHASE {
+                return Ok(Self {
+                    divisor,
+                    wr_low: low as u8,
+                    wr_high: high as u8,
+                });
+            }
+        }
+        Err(TimingError::ClockTooFast)
+    }
+
+    /// Actual write cycle at `sys_clk`, in ns.
+    pub fn write_cycle_ns(&self, sys_clk: HertzU32) -> u32 {
+        let ps = (self.wr_low as u64 + self.wr_high as u64) * self.divisor as u64 * PS_PER_S
+            / sys_clk.to_Hz() as u64;
+        (ps / 1000) as u32
+    }
+
+    /// Checks that this timing still meets the panel limits at `sys_clk`,
+    /// e.g. after switching to one of the `overclock_configs`.
+    pub fn verify(&self, sys_clk: HertzU32) -> Result<(), TimingError> {
+        let period = period_ps(sys_clk, self.divisor);
+        if (self.wr_low as u32) < cycles(ILI9488_T_WRL_NS, period)
+            || (self.wr_high as u32) < cycles(ILI9488_T_WRH_NS, period)
+        {
+            return Err(TimingError::StrobeTooShort);
+        }
+        let write_cycle_ns = self.write_cycle_ns(sys_clk);
+        if write_cycle_ns < ILI9488_T_WC_NS {
+            return Err(TimingError::WriteCycleTooShort {
+                write_cycle_ns,
+                min_ns: ILI9488_T_WC_NS,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fugit::RateExtU32;
+
+    #[test]
+    fn stock_clock() {
+        // 125MHz: 8ns per cycle, 2 + 2 cycles cover 15ns + 15ns.
+        let timing = BusTiming::new(125.MHz(), ILI9488_T_WC_NS).unwrap();
+        assert_eq!(
+            timing,
+            BusTiming {
+                divisor: 1,
+                wr_low: 2,
+                wr_high: 2
+            }
+        );
+        assert_eq!(timing.write_cycle_ns(125.MHz()), 32);
+    }
+
+    #[test]
+    fn overclocked() {
+        let timing = BusTiming::new(240.MHz(), ILI9488_T_WC_NS).unwrap();
+        assert_eq!((timing.divisor, timing.wr_low, timing.wr_high), (1, 4, 4));
+        assert!(timing.write_cycle_ns(240.MHz()) >= ILI9488_T_WC_NS);
+        timing.verify(240.MHz()).unwrap();
+    }
+
+    #[test]
+    fn slow_cycle_is_split_evenly() {
+        let timing = BusTiming::new(125.MHz(), 100).unwrap();
+        assert_eq!((timing.divisor, timing.wr_low, timing.wr_high), (1, 6, 7));
+    }
+
+    #[test]
+    fn long_cycle_raises_divisor() {
+        let timing = BusTiming::new(400.MHz(), 1000).unwrap();
+        assert!(timing.divisor > 1);
+        assert!(timing.wr_low as u32 <= MAX_PHASE && timing.wr_high as u32 <= MAX_PHASE);
+        assert!(timing.write_cycle_ns(400.MHz()) >= 1000);
+    }
+
+    #[test]
+    fn too_short_cycle_is_rejected() {
+        assert_eq!(
+            BusTiming::new(125.MHz(), 10),
+            Err(TimingError::WriteCycleTooShort {
+                write_cycle_ns: 10,
+                min_ns: ILI9488_T_WC_NS
+            })
+        );
+    }
+
+    #[test]
+    fn overclock_breaks_timing() {
+        let timing = BusTiming::new(125.MHz(), ILI9488_T_WC_NS).unwrap();
+        assert_eq!(timing.verify(400.MHz()), Err(TimingError::StrobeTooShort));
+    }
+}
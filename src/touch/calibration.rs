@@ -0,0 +1,422 @@
+use display_interface::{DisplayError, WriteOnlyDataCommand};
+use embedded_graphics::{
+    prelude::{OriginDimensions, Point, Primitive, RgbColor, Size},
+    primitives::{Circle, Line, PrimitiveStyle},
+    Drawable,
+};
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+
+use super::RawTouch;
+use crate::{ColorFormat, Orientation, ILI9488};
+
+/// Fraction bits of the calibration coefficients.
+const SHIFT: u32 = 16;
+
+/// Time between touch samples on the calibration screen.
+const SAMPLE_INTERVAL_MS: u32 = 10;
+
+/// A crosshair on the panel and the raw sample read while it was touched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CalibrationPoint {
+    /// Crosshair position in panel coordinates, see
+    /// [`Orientation::to_panel`].
+    pub panel: Point,
+    pub raw: (u16, u16),
+}
+
+/// Affine map from raw touch samples to panel coordinates,
+/// `x = (a * raw_x + b * raw_y + c) / 2^16` and
+/// `y = (d * raw_x + e * raw_y + f) / 2^16`.
+///
+/// Panel coordinates are those of `Landscape(false)`, so one calibration
+/// serves every orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// `[a, b, c, d, e, f]`, with 16 fraction bits.
+    pub coefficients: [i32; 6],
+    /// Panel size in `Landscape(false)`.
+    pub panel: Size,
+}
+
+impl Calibration {
+    /// Least-squares fit of `points`, exact with 3 of them. `None` with
+    /// fewer points or all of them on a line.
+    pub fn from_points(points: &[CalibrationPoint], panel: Size) -> Option<Self> {
+        if points.len() < 3 {
+            return None;
+        }
+
+        // Normal equations M * [a b c] = vx and M * [d e f] = vy, solved
+        // exactly by Cramer's rule.
+        let mut m = [[0i128; 3]; 3];
+        let (mut vx, mut vy) = ([0i128; 3], [0i128; 3]);
+        for p in points {
+            let r = [p.raw.0 as i128, p.raw.1 as i128, 1];
+            for (i, ri) in r.iter().enumerate() {
+                for (mij, rj) in m[i].iter_mut().zip(r) {
+                    *mij += ri * rj;
+                }
+                vx[i] += ri * p.panel.x as i128;
+                vy[i] += ri * p.panel.y as i128;
+            }
+        }
+        let det = det3(&m);
+        if det == 0 {
+            return None;
+        }
+
+        let [a, b, c] = solve(&m, det, &vx)?;
+        let [d, e, f] = solve(&m, det, &vy)?;
+        Some(Self {
+            coefficients: [a, b, c, d, e, f],
+            panel,
+        })
+    }
+
+    /// Panel position of the raw sample `raw`, kept on the panel.
+    pub fn map(&self, raw: (u16, u16)) -> Point {
+        let [a, b, c, d, e, f] = self.coefficients.map(i64::from);
+        let (rx, ry) = (raw.0 as i64, raw.1 as i64);
+        let half = 1 << (SHIFT - 1);
+        let x = (a * rx + b * ry + c + half) >> SHIFT;
+        let y = (d * rx + e * ry + f + half) >> SHIFT;
+        Point::new(
+            x.clamp(0, self.panel.width as i64 - 1) as i32,
+            y.clamp(0, self.panel.height as i64 - 1) as i32,
+        )
+    }
+
+    /// Display position of the raw sample `raw` in `orientation`.
+    pub fn to_screen(&self, raw: (u16, u16), orientation: Orientation) -> Point {
+        orientation.from_panel(self.map(raw), self.panel)
+    }
+}
+
+fn det3(m: &[[i128; 3]; 3]) -> i128 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Solution of `m * x = v` with `det` the determinant of `m`, in fixed
+/// point. `None` if a coefficient doesn't fit.
+fn solve(m: &[[i128; 3]; 3], det: i128, v: &[i128; 3]) -> Option<[i32; 3]> {
+    let mut x = [0; 3];
+    for (k, xk) in x.iter_mut().enumerate() {
+        let mut mk = *m;
+        for (row, vi) in mk.iter_mut().zip(v) {
+            row[k] = *vi;
+        }
+        *xk = i32::try_from(div_round(det3(&mk) << SHIFT, det)).ok()?;
+    }
+    Some(x)
+}
+
+fn div_round(n: i128, d: i128) -> i128 {
+    let (n, d) = if d < 0 { (-n, -d) } else { (n, d) };
+    if n < 0 {
+        (n - d / 2) / d
+    } else {
+        (n + d / 2) / d
+    }
+}
+
+/// Crosshairs shown by [`calibrate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationMode {
+    /// Top left, right middle and bottom middle, the least an affine map
+    /// needs.
+    ThreePoint,
+    /// The four corners and the centre, averaging out touch errors.
+    FivePoint,
+}
+
+impl CalibrationMode {
+    /// Crosshair positions on a screen of `size`, a tenth of it in from the
+    /// edges.
+    pub fn targets(self, size: Size) -> impl Iterator<Item = Point> {
+        let tenths: &[(u32, u32)] = match self {
+            CalibrationMode::ThreePoint => &[(1, 1), (9, 5), (5, 9)],
+            CalibrationMode::FivePoint => &[(1, 1), (9, 1), (9, 9), (1, 9), (5, 5)],
+        };
+        tenths.iter().map(move |&(x, y)| {
+            Point::new((size.width * x / 10) as i32, (size.height * y / 10) as i32)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum CalibrationError<E> {
+    Display(DisplayError),
+    Touch(E),
+    /// The touches don't give a usable map, e.g. they all lie on a line.
+    Degenerate,
+}
+
+impl<E> From<DisplayError> for CalibrationError<E> {
+    fn from(e: DisplayError) -> Self {
+        CalibrationError::Display(e)
+    }
+}
+
+/// Runs the calibration screen: shows each crosshair of `mode` in turn
+/// and waits for it to be tapped, then clears the screen and fits a
+/// [`Calibration`] to the taps.
+///
+/// The crosshairs are placed in the current orientation, the calibration
+/// holds for all of them.
+pub fn calibrate<DI, RST, BL, C, TE, T>(
+    display: &mut ILI9488<DI, RST, BL, C, TE>,
+    touch: &mut T,
+    mode: CalibrationMode,
+    delay_source: &mut impl DelayNs,
+) -> Result<Calibration, CalibrationError<T::Error>>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat + RgbColor,
+    T: RawTouch,
+{
+    let orientation = display.orientation();
+    let size = display.size();
+    let panel = if orientation.is_landscape() {
+        size
+    } else {
+        Size::new(size.height, size.width)
+    };
+
+    let mut points = heapless::Vec::<CalibrationPoint, 5>::new();
+    for target in mode.targets(size) {
+        display.clear(C::BLACK)?;
+        draw_crosshair(display, target, C::WHITE)?;
+        let raw = wait_for_tap(touch, delay_source).map_err(CalibrationError::Touch)?;
+        let point = CalibrationPoint {
+            panel: orientation.to_panel(target, panel),
+            raw,
+        };
+        points.push(point).unwrap();
+    }
+    display.clear(C::BLACK)?;
+
+    Calibration::from_points(&points, panel).ok_or(CalibrationError::Degenerate)
+}
+
+fn draw_crosshair<DI, RST, BL, C, TE>(
+    display: &mut ILI9488<DI, RST, BL, C, TE>,
+    center: Point,
+    color: C,
+) -> Result<(), DisplayError>
+where
+    DI: WriteOnlyDataCommand,
+    RST: OutputPin,
+    BL: OutputPin,
+    C: ColorFormat,
+{
+    let style = PrimitiveStyle::with_stroke(color, 1);
+    let (dx, dy) = (Point::new(10, 0), Point::new(0, 10));
+    Line::new(center - dx, center + dx)
+        .into_styled(style)
+        .draw(display)?;
+    Line::new(center - dy, center + dy)
+        .into_styled(style)
+        .draw(display)?;
+    Circle::with_center(center, 13)
+        .into_styled(style)
+        .draw(display)
+}
+
+/// Averages the samples of the next touch, returning once it is released.
+fn wait_for_tap<T: RawTouch>(
+    touch: &mut T,
+    delay_source: &mut impl DelayNs,
+) -> Result<(u16, u16), T::Error> {
+    let (mut sum_x, mut sum_y, mut count) = (0u32, 0u32, 0u32);
+    loop {
+        match touch.read_raw()? {
+            Some((x, y)) => {
+                sum_x += x as u32;
+                sum_y += y as u32;
+                count += 1;
+            }
+            None if count > 0 => break,
+            None => {}
+        }
+        delay_source.delay_ms(SAMPLE_INTERVAL_MS);
+    }
+    Ok(((sum_x / count) as u16, (sum_y / count) as u16))
+}
+
+/// Touch controller with its samples mapped to display coordinates.
+pub struct CalibratedTouch<T> {
+    touch: T,
+    calibration: Calibration,
+}
+
+impl<T: RawTouch> CalibratedTouch<T> {
+    pub fn new(touch: T, calibration: Calibration) -> Self {
+        Self { touch, calibration }
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn release(self) -> T {
+        self.touch
+    }
+
+    /// The touched point on a display in `orientation`, which should be
+    /// `ILI9488::orientation()`.
+    pub fn read(&mut self, orientation: Orientation) -> Result<Option<Point>, T::Error> {
+        let raw = self.touch.read_raw()?;
+        Ok(raw.map(|raw| self.calibration.to_screen(raw, orientation)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::mock::{MockDelay, MockInterface, MockTouch, NoopPin};
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    const PANEL: Size = Size::new(480, 320);
+
+    /// A panel with its touch layer turned by 90 degrees: raw X runs down
+    /// the panel and raw Y from right to left.
+    fn raw_at(panel: Point) -> (u16, u16) {
+        let x = 180 + panel.y * 3700 / 320;
+        let y = 3900 - panel.x * 3650 / 480;
+        (x as u16, y as u16)
+    }
+
+    fn calibration_point(x: i32, y: i32) -> CalibrationPoint {
+        let panel = Point::new(x, y);
+        CalibrationPoint {
+            panel,
+            raw: raw_at(panel),
+        }
+    }
+
+    fn assert_close(a: Point, b: Point) {
+        let d = a - b;
+        assert!(d.x.abs() <= 1 && d.y.abs() <= 1, "{a:?} vs {b:?}");
+    }
+
+    #[test]
+    fn three_points_fit_exactly() {
+        let points = [
+            calibration_point(48, 32),
+            calibration_point(432, 160),
+            calibration_point(240, 288),
+        ];
+        let calibration = Calibration::from_points(&points, PANEL).unwrap();
+        for p in points {
+            assert_eq!(calibration.map(p.raw), p.panel);
+        }
+        for (x, y) in [(0, 0), (479, 319), (100, 250), (333, 17)] {
+            let p = Point::new(x, y);
+            assert_close(calibration.map(raw_at(p)), p);
+        }
+    }
+
+    #[test]
+    fn five_points_average_out_noise() {
+        let noise = [(3, -2), (-3, 1), (2, 3), (-1, -3), (0, 2)];
+        let points: Vec<_> = CalibrationMode::FivePoint
+            .targets(PANEL)
+            .zip(noise)
+            .map(|(p, (nx, ny))| {
+                let (x, y) = raw_at(p);
+                CalibrationPoint {
+                    panel: p,
+                    raw: ((x as i32 + nx) as u16, (y as i32 + ny) as u16),
+                }
+            })
+            .collect();
+        let calibration = Calibration::from_points(&points, PANEL).unwrap();
+        for (x, y) in [(10, 10), (470, 310), (240, 160), (60, 280)] {
+            let p = Point::new(x, y);
+            assert_close(calibration.map(raw_at(p)), p);
+        }
+    }
+
+    #[test]
+    fn degenerate_points() {
+        let line = [
+            calibration_point(0, 0),
+            calibration_point(100, 100),
+            calibration_point(200, 200),
+        ];
+        assert_eq!(Calibration::from_points(&line, PANEL), None);
+        assert_eq!(Calibration::from_points(&line[..2], PANEL), None);
+    }
+
+    #[test]
+    fn mapped_points_stay_on_the_panel() {
+        let points = [
+            calibration_point(48, 32),
+            calibration_point(432, 160),
+            calibration_point(240, 288),
+        ];
+        let calibration = Calibration::from_points(&points, PANEL).unwrap();
+        assert_eq!(calibration.map((0, 4095)), Point::new(0, 0));
+        assert_eq!(calibration.map((4095, 0)), Point::new(479, 319));
+    }
+
+    #[test]
+    fn calibration_screen_in_portrait() {
+        let mut display: ILI9488<_, NoopPin, NoopPin> =
+            ILI9488::new(MockInterface::new(), None, None, 480, 320);
+        let portrait = Orientation::Portrait(false);
+        display.set_orientation(portrait).unwrap();
+
+        // Taps right on every crosshair, read three times each.
+        let taps = CalibrationMode::FivePoint
+            .targets(display.size())
+            .flat_map(|p| {
+                let panel = portrait.to_panel(p, PANEL);
+                MockTouch::tap(raw_at(panel), 3)
+            });
+        let mut touch = MockTouch::new(taps);
+        let mut delay = MockDelay::default();
+        let calibration = calibrate(
+            &mut display,
+            &mut touch,
+            CalibrationMode::FivePoint,
+            &mut delay,
+        )
+        .unwrap();
+        assert_eq!(calibration.panel, PANEL);
+
+        // The same calibration serves every orientation.
+        let mut touch = CalibratedTouch::new(MockTouch::new([]), calibration);
+        for orientation in [portrait, Orientation::LandscapeFlipped(true)] {
+            let logical = Point::new(30, 200);
+            let raw = raw_at(orientation.to_panel(logical, PANEL));
+            touch.touch = MockTouch::new([Some(raw), None]);
+            assert_close(touch.read(orientation).unwrap().unwrap(), logical);
+            assert_eq!(touch.read(orientation).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn crosshair() {
+        let mut display: ILI9488<_, NoopPin, NoopPin> =
+            ILI9488::new(Emulator::new(), None, None, 480, 320);
+        display.init(&mut MockDelay::default()).unwrap();
+        draw_crosshair(&mut display, Point::new(48, 32), Rgb565::WHITE).unwrap();
+
+        let emu = display.di_mut();
+        let white = [0xFF; 3];
+        for (x, y) in [(48, 32), (38, 32), (58, 32), (48, 22), (48, 42)] {
+            assert_eq!(emu.pixel(x, y), white, "({x}, {y})");
+        }
+        assert_eq!(emu.pixel(46, 34), [0; 3]);
+    }
+}
@@ -0,0 +1,309 @@
+use embedded_graphics::prelude::Point;
+
+use super::RawTouch;
+
+/// Longest median window.
+pub const MAX_MEDIAN: usize = 9;
+
+/// Stages of a [`TouchFilter`], applied in field order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterConfig {
+    /// Samples the median is taken over, per axis, up to [`MAX_MEDIAN`].
+    /// 1 turns the stage off.
+    pub median: usize,
+    /// Weight of a new sample in the running average, in 1/256. 256 turns
+    /// the stage off, lower values smooth more and lag more.
+    pub iir_weight: u16,
+    /// Moves of at most this much on both axes are dropped, so a resting
+    /// finger gives a steady point. 0 turns the stage off.
+    pub dead_band: u32,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            median: 5,
+            iir_weight: 128,
+            dead_band: 2,
+        }
+    }
+}
+
+/// Median, running average and dead-band over a stream of touch points,
+/// in whatever units they come.
+///
+/// Every stage restarts when the pen lifts, and no point comes out of a
+/// new touch until the median window is full, which also drops the
+/// noisiest samples of the touch down.
+pub struct TouchFilter {
+    config: FilterConfig,
+    window: [Point; MAX_MEDIAN],
+    /// Samples in `window`, and the slot the next one goes to.
+    len: usize,
+    next: usize,
+    /// Running average, with 8 fraction bits.
+    average: Option<Point>,
+    last: Option<Point>,
+}
+
+impl TouchFilter {
+    pub fn new(config: FilterConfig) -> Self {
+        let config = FilterConfig {
+            median: config.median.clamp(1, MAX_MEDIAN),
+            iir_weight: config.iir_weight.clamp(1, 256),
+            ..config
+        };
+        Self {
+            config,
+            window: [Point::zero(); MAX_MEDIAN],
+            len: 0,
+            next: 0,
+            average: None,
+            last: None,
+        }
+    }
+
+    pub fn config(&self) -> FilterConfig {
+        self.config
+    }
+
+    /// Forgets the current touch.
+    pub fn reset(&mut self) {
+        (self.len, self.next) = (0, 0);
+        (self.average, self.last) = (None, None);
+    }
+
+    /// Feeds the next sample, `None` while the panel isn't touched, and
+    /// returns the filtered point.
+    pub fn update(&mut self, sample: Option<Point>) -> Option<Point> {
+        let Some(sample) = sample else {
+            self.reset();
+            return None;
+        };
+        let median = self.median(sample)?;
+        let average = self.average(median);
+        Some(self.dead_band(average))
+    }
+
+    fn median(&mut self, sample: Point) -> Option<Point> {
+        let n = self.config.median;
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % n;
+        self.len = (self.len + 1).min(n);
+        if self.len < n {
+            return None;
+        }
+
+        let (mut xs, mut ys) = ([0; MAX_MEDIAN], [0; MAX_MEDIAN]);
+        for (i, p) in self.window[..n].iter().enumerate() {
+            (xs[i], ys[i]) = (p.x, p.y);
+        }
+        xs[..n].sort_unstable();
+        ys[..n].sort_unstable();
+        Some(Point::new(xs[n / 2], ys[n / 2]))
+    }
+
+    fn average(&mut self, point: Point) -> Point {
+        let scaled = point * 256;
+        let w = self.config.iir_weight as i32;
+        let average = match self.average {
+            Some(avg) => avg + (scaled - avg) * w / 256,
+            None => scaled,
+        };
+        self.average = Some(average);
+        Point::new((average.x + 128) >> 8, (average.y + 128) >> 8)
+    }
+
+    fn dead_band(&mut self, point: Point) -> Point {
+        let band = self.config.dead_band;
+        if let Some(last) = self.last {
+            let d = point - last;
+            if d.x.unsigned_abs() <= band && d.y.unsigned_abs() <= band {
+                return last;
+            }
+        }
+        self.last = Some(point);
+        point
+    }
+}
+
+/// Touch controller with a [`TouchFilter`] on its raw samples, to be put
+/// under a `CalibratedTouch`.
+pub struct FilteredTouch<T> {
+    touch: T,
+    filter: TouchFilter,
+}
+
+impl<T: RawTouch> FilteredTouch<T> {
+    pub fn new(touch: T, config: FilterConfig) -> Self {
+        Self {
+            touch,
+            filter: TouchFilter::new(config),
+        }
+    }
+
+    pub fn filter_mut(&mut self) -> &mut TouchFilter {
+        &mut self.filter
+    }
+
+    pub fn release(self) -> T {
+        self.touch
+    }
+}
+
+impl<T: RawTouch> RawTouch for FilteredTouch<T> {
+    type Error = T::Error;
+
+    fn read_raw(&mut self) -> Result<Option<(u16, u16)>, T::Error> {
+        let sample = self.touch.read_raw()?;
+        let point = self
+            .filter
+            .update(sample.map(|(x, y)| Point::new(x as i32, y as i32)));
+        Ok(point.map(|p| (p.x as u16, p.y as u16)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTouch;
+
+    const OFF: FilterConfig = FilterConfig {
+        median: 1,
+        iir_weight: 256,
+        dead_band: 0,
+    };
+
+    fn run(config: FilterConfig, trace: &[Option<Point>]) -> Vec<Option<Point>> {
+        let mut filter = TouchFilter::new(config);
+        trace.iter().map(|&s| filter.update(s)).collect()
+    }
+
+    fn touched(points: &[(i32, i32)]) -> Vec<Option<Point>> {
+        points
+            .iter()
+            .map(|&(x, y)| Some(Point::new(x, y)))
+            .collect()
+    }
+
+    /// Deterministic noise in `-amplitude..=amplitude`.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self, amplitude: i32) -> i32 {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            ((self.0 >> 16) % (2 * amplitude as u32 + 1)) as i32 - amplitude
+        }
+    }
+
+    /// Synthetic handwriting: a slow diagonal stroke with noise on every
+    /// sample and a spike on every seventh.
+    fn stroke_trace() -> (Vec<Point>, Vec<Option<Point>>) {
+        let mut noise = Noise(1);
+        let truth: Vec<_> = (0..120)
+            .map(|i| Point::new(1000 + 4 * i, 3000 - 3 * i))
+            .collect();
+        let trace = truth
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let spike = if i % 7 == 3 {
+                    Point::new(600, -450)
+                } else {
+                    Point::zero()
+                };
+                Some(p + Point::new(noise.next(12), noise.next(12)) + spike)
+            })
+            .collect();
+        (truth, trace)
+    }
+
+    #[test]
+    fn all_stages_off_pass_through() {
+        let (_, trace) = stroke_trace();
+        assert_eq!(run(OFF, &trace), trace);
+    }
+
+    #[test]
+    fn median_drops_spikes() {
+        let config = FilterConfig { median: 3, ..OFF };
+        let trace = touched(&[(100, 100), (101, 99), (900, 5), (100, 101), (99, 100)]);
+        let out = run(config, &trace);
+        assert_eq!(&out[..2], [None, None]);
+        assert_eq!(&out[2..], touched(&[(101, 99), (101, 99), (100, 100)]));
+    }
+
+    #[test]
+    fn average_follows_a_step() {
+        let config = FilterConfig {
+            iir_weight: 64,
+            ..OFF
+        };
+        let trace = touched(&[(0, 0), (400, 0), (400, 0), (400, 0), (400, 0)]);
+        let xs: Vec<_> = run(config, &trace).iter().map(|p| p.unwrap().x).collect();
+        assert_eq!(xs, [0, 100, 175, 231, 273]);
+    }
+
+    #[test]
+    fn dead_band_holds_a_resting_finger() {
+        let config = FilterConfig {
+            dead_band: 3,
+            ..OFF
+        };
+        let trace = touched(&[(50, 50), (52, 49), (47, 53), (50, 50), (54, 50), (55, 51)]);
+        let expected = touched(&[(50, 50), (50, 50), (50, 50), (50, 50), (54, 50), (54, 50)]);
+        assert_eq!(run(config, &trace), expected);
+    }
+
+    #[test]
+    fn lifting_the_pen_restarts() {
+        let config = FilterConfig {
+            median: 3,
+            iir_weight: 64,
+            dead_band: 2,
+        };
+        let mut trace = touched(&[(10, 10); 4]);
+        trace.push(None);
+        trace.extend(touched(&[(300, 200); 3]));
+        let out = run(config, &trace);
+        assert_eq!(out[4], None);
+        // Nothing of the old touch leaks into the new one.
+        assert_eq!(&out[5..], [None, None, Some(Point::new(300, 200))]);
+    }
+
+    #[test]
+    fn steady_handwriting() {
+        let (truth, trace) = stroke_trace();
+        let out = run(FilterConfig::default(), &trace);
+
+        let worst = |points: &[Option<Point>]| {
+            let errors = points
+                .iter()
+                .zip(&truth)
+                .filter_map(|(p, t)| Some((*p)? - *t));
+            errors.map(|d| d.x.abs().max(d.y.abs())).max().unwrap()
+        };
+        assert!(worst(&trace) > 400);
+        // The spikes are gone and the noise is down, behind a lag of a few
+        // samples along the stroke.
+        assert!(worst(&out) < 30);
+        assert_eq!(out.iter().filter(|p| p.is_none()).count(), 4);
+    }
+
+    #[test]
+    fn filtered_touch_over_a_controller() {
+        let samples = [
+            (2000, 1000),
+            (2004, 998),
+            (3500, 10),
+            (2002, 1001),
+            (1999, 1000),
+        ];
+        let mut touch = FilteredTouch::new(
+            MockTouch::new(samples.map(Some).into_iter().chain([None])),
+            FilterConfig::default(),
+        );
+        let out: Vec<_> = (0..6).map(|_| touch.read_raw().unwrap()).collect();
+        assert_eq!(out, [None, None, None, None, Some((2002, 1000)), None]);
+    }
+}
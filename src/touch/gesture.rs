@@ -0,0 +1,366 @@
+use embedded_graphics::prelude::Point;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Events recognised by a [`GestureDetector`], in the units of the points
+/// fed to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// A short touch that stayed in place, where it started.
+    Tap(Point),
+    /// A tap right after another one at the same place, instead of a
+    /// second `Tap`.
+    DoubleTap(Point),
+    /// A touch held in place, reported once while still held.
+    LongPress(Point),
+    /// A fast move ending with the touch, with its speed along the
+    /// direction in units per second.
+    Swipe(SwipeDirection, u32),
+    /// The touch moved from `start` to `position`, reported on every move.
+    Drag { start: Point, position: Point },
+}
+
+/// Thresholds of a [`GestureDetector`], distances in the units of the
+/// points and times in ms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// Distance a touch can wander and still count as in place.
+    pub slop: u32,
+    /// Longest touch that is a tap.
+    pub tap_max_ms: u32,
+    /// Longest time from the end of a tap to the start of the next one
+    /// that makes a double tap.
+    pub double_tap_ms: u32,
+    /// Time a touch has to be held for a long press.
+    pub long_press_ms: u32,
+    /// Shortest move along the direction that is a swipe.
+    pub swipe_min_distance: u32,
+    /// Lowest speed that is a swipe, in units per second.
+    pub swipe_min_velocity: u32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            slop: 10,
+            tap_max_ms: 250,
+            double_tap_ms: 300,
+            long_press_ms: 600,
+            swipe_min_distance: 60,
+            swipe_min_velocity: 300,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Up,
+    Down {
+        start: Point,
+        start_ms: u32,
+        last: Point,
+        dragging: bool,
+        long_pressed: bool,
+    },
+}
+
+/// Turns timestamped touch samples into [`Gesture`]s.
+///
+/// Pure logic: feed it every sample with [`update`](Self::update),
+/// whatever the controller or filter behind them.
+pub struct GestureDetector {
+    config: GestureConfig,
+    state: State,
+    /// Position and end time of the last tap, for double taps.
+    last_tap: Option<(Point, u32)>,
+}
+
+impl GestureDetector {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            state: State::Up,
+            last_tap: None,
+        }
+    }
+
+    pub fn config(&self) -> GestureConfig {
+        self.config
+    }
+
+    /// Feeds the sample taken at `now_ms`, `None` while the panel isn't
+    /// touched. The clock may wrap around.
+    pub fn update(&mut self, now_ms: u32, sample: Option<Point>) -> Option<Gesture> {
+        match (self.state, sample) {
+            (State::Up, None) => None,
+            (State::Up, Some(point)) => {
+                self.state = State::Down {
+                    start: point,
+                    start_ms: now_ms,
+                    last: point,
+                    dragging: false,
+                    long_pressed: false,
+                };
+                None
+            }
+            (State::Down { .. }, Some(point)) => self.touched(now_ms, point),
+            (
+                State::Down {
+                    start,
+                    start_ms,
+                    last,
+                    dragging,
+                    long_pressed,
+                },
+                None,
+            ) => {
+                self.state = State::Up;
+                let duration_ms = now_ms.wrapping_sub(start_ms);
+                if dragging {
+                    self.swipe(last - start, duration_ms)
+                } else if !long_pressed && duration_ms <= self.config.tap_max_ms {
+                    Some(self.tap(start, start_ms, now_ms))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn touched(&mut self, now_ms: u32, point: Point) -> Option<Gesture> {
+        let State::Down {
+            start,
+            start_ms,
+            last,
+            dragging,
+            long_pressed,
+        } = &mut self.state
+        else {
+            unreachable!()
+        };
+
+        let moved = point != *last;
+        *last = point;
+        if !*dragging && distance(point - *start) > self.config.slop {
+            *dragging = true;
+        } else if !*dragging {
+            let held_ms = now_ms.wrapping_sub(*start_ms);
+            if !*long_pressed && held_ms >= self.config.long_press_ms {
+                *long_pressed = true;
+                return Some(Gesture::LongPress(*start));
+            }
+            return None;
+        }
+        moved.then_some(Gesture::Drag {
+            start: *start,
+            position: point,
+        })
+    }
+
+    fn tap(&mut self, point: Point, start_ms: u32, end_ms: u32) -> Gesture {
+        if let Some((last, last_end_ms)) = self.last_tap.take() {
+            let gap_ms = start_ms.wrapping_sub(last_end_ms);
+            if gap_ms <= self.config.double_tap_ms && distance(point - last) <= self.config.slop {
+                return Gesture::DoubleTap(point);
+            }
+        }
+        self.last_tap = Some((point, end_ms));
+        Gesture::Tap(point)
+    }
+
+    fn swipe(&self, moved: Point, duration_ms: u32) -> Option<Gesture> {
+        let (along, direction) = if moved.x.abs() >= moved.y.abs() {
+            let direction = if moved.x < 0 {
+                SwipeDirection::Left
+            } else {
+                SwipeDirection::Right
+            };
+            (moved.x.unsigned_abs(), direction)
+        } else {
+            let direction = if moved.y < 0 {
+                SwipeDirection::Up
+            } else {
+                SwipeDirection::Down
+            };
+            (moved.y.unsigned_abs(), direction)
+        };
+        let velocity = (along as u64 * 1000 / duration_ms.max(1) as u64) as u32;
+        let config = &self.config;
+        (along >= config.swipe_min_distance && velocity >= config.swipe_min_velocity)
+            .then_some(Gesture::Swipe(direction, velocity))
+    }
+}
+
+/// Chebyshev length of `d`, the larger of its two components.
+fn distance(d: Point) -> u32 {
+    d.x.unsigned_abs().max(d.y.unsigned_abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A touch sampled every 10 ms from `t0`, and the release after it.
+    fn touch(t0: u32, points: &[(i32, i32)]) -> Vec<(u32, Option<Point>)> {
+        let mut trace: Vec<_> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| (t0.wrapping_add(10 * i as u32), Some(Point::new(x, y))))
+            .collect();
+        trace.push((t0.wrapping_add(10 * points.len() as u32), None));
+        trace
+    }
+
+    fn held(point: (i32, i32), ms: u32) -> Vec<(i32, i32)> {
+        vec![point; (ms / 10) as usize]
+    }
+
+    fn gestures(config: GestureConfig, trace: &[(u32, Option<Point>)]) -> Vec<Gesture> {
+        let mut detector = GestureDetector::new(config);
+        trace
+            .iter()
+            .filter_map(|&(t, p)| detector.update(t, p))
+            .collect()
+    }
+
+    fn detect(trace: &[(u32, Option<Point>)]) -> Vec<Gesture> {
+        gestures(GestureConfig::default(), trace)
+    }
+
+    #[test]
+    fn tap() {
+        let trace = touch(1000, &[(50, 60), (52, 58), (49, 61)]);
+        assert_eq!(detect(&trace), [Gesture::Tap(Point::new(50, 60))]);
+    }
+
+    #[test]
+    fn slow_touch_is_no_tap() {
+        let trace = touch(0, &held((50, 60), 400));
+        assert_eq!(detect(&trace), []);
+    }
+
+    #[test]
+    fn long_press_fires_once_while_held() {
+        let trace = touch(0, &held((200, 100), 1000));
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        let events: Vec<_> = trace
+            .iter()
+            .filter_map(|&(t, p)| Some((t, detector.update(t, p)?)))
+            .collect();
+        assert_eq!(events, [(600, Gesture::LongPress(Point::new(200, 100)))]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut trace = touch(0, &[(80, 80), (81, 80)]);
+        trace.extend(touch(200, &[(84, 77), (84, 78)]));
+        trace.extend(touch(400, &[(80, 80)]));
+        let expected = [
+            Gesture::Tap(Point::new(80, 80)),
+            Gesture::DoubleTap(Point::new(84, 77)),
+            Gesture::Tap(Point::new(80, 80)),
+        ];
+        assert_eq!(detect(&trace), expected);
+    }
+
+    #[test]
+    fn taps_apart_stay_taps() {
+        // Too late for a double tap.
+        let mut trace = touch(0, &[(80, 80)]);
+        trace.extend(touch(400, &[(80, 80)]));
+        // Too far away.
+        trace.extend(touch(500, &[(120, 80)]));
+        let taps: Vec<_> = [(80, 80), (80, 80), (120, 80)]
+            .map(|(x, y)| Gesture::Tap(Point::new(x, y)))
+            .into();
+        assert_eq!(detect(&trace), taps);
+    }
+
+    #[test]
+    fn fast_move_is_a_swipe() {
+        let points: Vec<_> = (0..6).map(|i| (300 - 40 * i, 100 + i)).collect();
+        let events = detect(&touch(0, &points));
+        let start = Point::new(300, 100);
+        let drags: Vec<_> = points[1..]
+            .iter()
+            .map(|&(x, y)| Gesture::Drag {
+                start,
+                position: Point::new(x, y),
+            })
+            .collect();
+        // 200 to the left in 60 ms.
+        assert_eq!(events[..5], drags);
+        assert_eq!(events[5..], [Gesture::Swipe(SwipeDirection::Left, 3333)]);
+    }
+
+    #[test]
+    fn swipe_directions() {
+        let cases = [
+            ((100, 100), (200, 110), SwipeDirection::Right),
+            ((100, 300), (90, 100), SwipeDirection::Up),
+            ((100, 100), (130, 250), SwipeDirection::Down),
+        ];
+        for (from, to, direction) in cases {
+            let events = detect(&touch(0, &[from, to]));
+            assert!(
+                matches!(events.last(), Some(Gesture::Swipe(d, _)) if *d == direction),
+                "{events:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn slow_drag_is_no_swipe() {
+        let points: Vec<_> = (0..50).map(|i| (100 + 2 * i, 100)).collect();
+        let events = detect(&touch(0, &points));
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|e| matches!(e, Gesture::Drag { start, .. } if *start == Point::new(100, 100))));
+    }
+
+    #[test]
+    fn drag_after_long_press() {
+        let mut points = held((10, 10), 700);
+        points.push((40, 10));
+        points.push((40, 10));
+        let events = detect(&touch(0, &points));
+        let expected = [
+            Gesture::LongPress(Point::new(10, 10)),
+            Gesture::Drag {
+                start: Point::new(10, 10),
+                position: Point::new(40, 10),
+            },
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let config = GestureConfig {
+            slop: 2,
+            long_press_ms: 100,
+            ..GestureConfig::default()
+        };
+        let trace = touch(0, &[(50, 50), (54, 50)]);
+        let events = gestures(config, &trace);
+        assert!(matches!(events[..], [Gesture::Drag { .. }]));
+        let trace = touch(0, &held((50, 50), 150));
+        let expected = [Gesture::LongPress(Point::new(50, 50))];
+        assert_eq!(gestures(config, &trace), expected);
+    }
+
+    #[test]
+    fn clock_wraps_around() {
+        let mut trace = touch(u32::MAX - 15, &[(5, 5), (5, 5)]);
+        trace.extend(touch(100, &[(5, 5)]));
+        let p = Point::new(5, 5);
+        assert_eq!(detect(&trace), [Gesture::Tap(p), Gesture::DoubleTap(p)]);
+    }
+}
@@ -0,0 +1,21 @@
+//! Resistive touch panel support.
+
+mod calibration;
+mod filter;
+mod gesture;
+mod tsc2007;
+
+pub use calibration::{
+    calibrate, CalibratedTouch, Calibration, CalibrationError, CalibrationMode, CalibrationPoint,
+};
+pub use filter::{FilterConfig, FilteredTouch, TouchFilter, MAX_MEDIAN};
+pub use gesture::{Gesture, GestureConfig, GestureDetector, SwipeDirection};
+pub use tsc2007::{Error, PowerDown, PressureThreshold, Resolution, Sample, Tsc2007};
+
+/// Touch controller reporting raw samples, whatever their scale.
+pub trait RawTouch {
+    type Error;
+
+    /// Raw X and Y samples while the panel is touched.
+    fn read_raw(&mut self) -> Result<Option<(u16, u16)>, Self::Error>;
+}
@@ -0,0 +1,484 @@
+use embedded_hal::{
+    digital::{self, InputPin},
+    i2c::{self, I2c},
+};
+
+use super::RawTouch;
+
+type Result<T, IRQ, I2C> = core::result::Result<
+    T,
+    Error<<IRQ as digital::ErrorType>::Error, <I2C as i2c::ErrorType>::Error>,
+>;
+
+/// Address with A1 and A0 tied low.
+const DEFAULT_ADDR: u8 = 0x48;
+/// Resistance across the X plate, typical of 3.5" panels.
+const DEFAULT_X_PLATE_OHMS: u16 = 300;
+
+/// Converter functions, the high nibble of the command byte.
+const MEASURE_TEMP0: u8 = 0x0;
+const MEASURE_X: u8 = 0xC;
+const MEASURE_Y: u8 = 0xD;
+const MEASURE_Z1: u8 = 0xE;
+const MEASURE_Z2: u8 = 0xF;
+
+/// Power-down mode, bits PD1-PD0 of every command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PowerDown {
+    /// Powers down after each conversion with PENIRQ enabled, so pen
+    /// touches can be seen between reads.
+    #[default]
+    BetweenConversions = 0b00,
+    /// Keeps the converter on and PENIRQ off, for quick reads in a row.
+    /// Reads then tell a contact from a non-zero Z1.
+    AdcOn = 0b01,
+}
+
+/// Conversion resolution, the M bit of every command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// Samples of 0..=4095, read as two bytes.
+    #[default]
+    Bits12 = 0,
+    /// Samples of 0..=255, read as a single byte.
+    Bits8 = 1,
+}
+
+/// Touch resistance limits of a valid contact, in ohms. A harder press
+/// has a lower resistance.
+///
+/// A contact starts once the resistance drops to `press` and lasts until
+/// it rises above `release`, which should be the larger of the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PressureThreshold {
+    pub press: u32,
+    pub release: u32,
+}
+
+/// One set of raw conversions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub x: u16,
+    pub y: u16,
+    pub z1: u16,
+    pub z2: u16,
+}
+
+impl Sample {
+    /// Touch resistance in ohms, `R_x-plate * X / full scale * (Z2 / Z1 - 1)`
+    /// with `full_scale` 4096 for 12-bit samples. `None` without contact,
+    /// when Z1 is 0.
+    pub fn resistance(&self, x_plate_ohms: u16, full_scale: u32) -> Option<u32> {
+        if self.z1 == 0 {
+            return None;
+        }
+        let (x, z1, z2) = (self.x as u64, self.z1 as u64, self.z2 as u64);
+        let rt = x_plate_ohms as u64 * x * z2.saturating_sub(z1) / (z1 * full_scale as u64);
+        Some(rt.min(u32::MAX as u64) as u32)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error<PinE, I2cE> {
+    Pin(PinE),
+    I2C(I2cE),
+}
+
+/// TSC2007 resistive touch screen controller on I2C, with its PENIRQ
+/// output on `irq`.
+///
+/// Positions are the raw samples of the converter, mapping them to screen
+/// coordinates is up to the caller.
+pub struct Tsc2007<IRQ, I2C> {
+    irq: IRQ,
+    i2c: I2C,
+    addr: u8,
+    power_down: PowerDown,
+    resolution: Resolution,
+    x_plate_ohms: u16,
+    threshold: Option<PressureThreshold>,
+    pen_down: bool,
+}
+
+impl<IRQ, I2C> Tsc2007<IRQ, I2C>
+where
+    IRQ: InputPin,
+    I2C: I2c,
+{
+    pub fn new(irq: IRQ, i2c: I2C) -> Self {
+        Self {
+            irq,
+            i2c,
+            addr: DEFAULT_ADDR,
+            power_down: PowerDown::default(),
+            resolution: Resolution::default(),
+            x_plate_ohms: DEFAULT_X_PLATE_OHMS,
+            threshold: None,
+            pen_down: false,
+        }
+    }
+
+    /// Uses the 7-bit address `addr`, 0x48 to 0x4B depending on A1 and A0.
+    pub fn with_address(mut self, addr: u8) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Sets the resistance across the X plate of the panel, from its data
+    /// sheet. It scales the touch resistance.
+    pub fn with_x_plate_ohms(mut self, ohms: u16) -> Self {
+        self.x_plate_ohms = ohms;
+        self
+    }
+
+    /// Makes `read` measure the touch resistance as well and report only
+    /// contacts within `threshold`, so light grazes and a lifting pen give
+    /// no points. `None`, the default, reports every touch PENIRQ shows,
+    /// or Z1 with [`PowerDown::AdcOn`].
+    pub fn set_pressure_threshold(&mut self, threshold: Option<PressureThreshold>) {
+        self.threshold = threshold;
+        self.pen_down = false;
+    }
+
+    /// Sets the power-down mode sent with the following commands.
+    pub fn set_power_down(&mut self, power_down: PowerDown) {
+        self.power_down = power_down;
+    }
+
+    /// Sets the resolution of the following conversions.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Puts the controller in its power-down mode, which also arms PENIRQ
+    /// with [`PowerDown::BetweenConversions`].
+    pub fn init(&mut self) -> Result<(), IRQ, I2C> {
+        let cmd = self.command(MEASURE_TEMP0);
+        self.i2c.write(self.addr, &[cmd]).map_err(Error::I2C)
+    }
+
+    pub fn release(self) -> (IRQ, I2C) {
+        (self.irq, self.i2c)
+    }
+
+    /// Whether the panel is touched, PENIRQ is low while it is. PENIRQ
+    /// stays high with [`PowerDown::AdcOn`].
+    pub fn is_pressed(&mut self) -> Result<bool, IRQ, I2C> {
+        self.irq.is_low().map_err(Error::Pin)
+    }
+
+    /// Whether the panel is touched, from PENIRQ or from Z1 where PENIRQ
+    /// is off.
+    fn touched(&mut self) -> Result<bool, IRQ, I2C> {
+        match self.power_down {
+            PowerDown::BetweenConversions => self.is_pressed(),
+            PowerDown::AdcOn => Ok(self.read_z1()? != 0),
+        }
+    }
+
+    pub fn read_x(&mut self) -> Result<u16, IRQ, I2C> {
+        self.measure(MEASURE_X)
+    }
+
+    pub fn read_y(&mut self) -> Result<u16, IRQ, I2C> {
+        self.measure(MEASURE_Y)
+    }
+
+    pub fn read_z1(&mut self) -> Result<u16, IRQ, I2C> {
+        self.measure(MEASURE_Z1)
+    }
+
+    pub fn read_z2(&mut self) -> Result<u16, IRQ, I2C> {
+        self.measure(MEASURE_Z2)
+    }
+
+    /// All four conversions while the panel is touched, `None` once Z1
+    /// shows no contact.
+    pub fn read_sample(&mut self) -> Result<Option<Sample>, IRQ, I2C> {
+        if self.power_down == PowerDown::BetweenConversions && !self.is_pressed()? {
+            return Ok(None);
+        }
+        let sample = Sample {
+            x: self.read_x()?,
+            y: self.read_y()?,
+            z1: self.read_z1()?,
+            z2: self.read_z2()?,
+        };
+        Ok(Some(sample).filter(|s| s.z1 != 0))
+    }
+
+    /// Touch resistance of `sample` in ohms, see [`Sample::resistance`].
+    pub fn resistance(&self, sample: &Sample) -> Option<u32> {
+        let full_scale = match self.resolution {
+            Resolution::Bits12 => 4096,
+            Resolution::Bits8 => 256,
+        };
+        sample.resistance(self.x_plate_ohms, full_scale)
+    }
+
+    /// Whether the last `read` saw a contact within the pressure threshold.
+    pub fn is_pen_down(&self) -> bool {
+        self.pen_down
+    }
+
+    /// Raw X and Y samples while the panel is touched, or pressed hard
+    /// enough with a pressure threshold set.
+    pub fn read(&mut self) -> Result<Option<(u16, u16)>, IRQ, I2C> {
+        let Some(threshold) = self.threshold else {
+            if !self.touched()? {
+                return Ok(None);
+            }
+            return Ok(Some((self.read_x()?, self.read_y()?)));
+        };
+
+        let sample = self.read_sample()?;
+        let limit = if self.pen_down {
+            threshold.release
+        } else {
+            threshold.press
+        };
+        let rt = sample.and_then(|s| self.resistance(&s));
+        self.pen_down = rt.is_some_and(|rt| rt <= limit);
+        Ok(sample.filter(|_| self.pen_down).map(|s| (s.x, s.y)))
+    }
+
+    fn command(&self, function: u8) -> u8 {
+        function << 4 | (self.power_down as u8) << 2 | (self.resolution as u8) << 1
+    }
+
+    /// Runs a conversion and reads back its result. 12-bit results come
+    /// MSB first and left aligned in two bytes.
+    fn measure(&mut self, function: u8) -> Result<u16, IRQ, I2C> {
+        let cmd = self.command(function);
+        let mut buf = [0; 2];
+        let len = match self.resolution {
+            Resolution::Bits12 => 2,
+            Resolution::Bits8 => 1,
+        };
+        self.i2c
+            .write_read(self.addr, &[cmd], &mut buf[..len])
+            .map_err(Error::I2C)?;
+        Ok(match self.resolution {
+            Resolution::Bits12 => (buf[0] as u16) << 4 | (buf[1] as u16) >> 4,
+            Resolution::Bits8 => buf[0] as u16,
+        })
+    }
+}
+
+impl<IRQ, I2C> RawTouch for Tsc2007<IRQ, I2C>
+where
+    IRQ: InputPin,
+    I2C: I2c,
+{
+    type Error = Error<IRQ::Error, I2C::Error>;
+
+    fn read_raw(&mut self) -> core::result::Result<Option<(u16, u16)>, Self::Error> {
+        self.read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockI2c, MockInputPin};
+    use embedded_hal::i2c::ErrorKind;
+
+    type Touch = Tsc2007<MockInputPin, MockI2c>;
+
+    fn touch(pressed: bool) -> Touch {
+        Tsc2007::new(MockInputPin::new(&[!pressed]), MockI2c::new())
+    }
+
+    /// Command bytes written so far.
+    fn commands(touch: &Touch) -> Vec<u8> {
+        touch
+            .i2c
+            .writes
+            .iter()
+            .map(|(addr, bytes)| {
+                assert_eq!(*addr, 0x48);
+                assert_eq!(bytes.len(), 1);
+                bytes[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn init_powers_down_with_penirq() {
+        let mut touch = touch(false);
+        touch.init().unwrap();
+        assert_eq!(commands(&touch), [0x00]);
+    }
+
+    #[test]
+    fn reads_raw_12bit_samples() {
+        let mut touch = touch(true);
+        touch.i2c.respond(&[0xAB, 0xC0]).respond(&[0x01, 0x20]);
+        assert_eq!(touch.read().unwrap(), Some((0xABC, 0x012)));
+        assert_eq!(commands(&touch), [0xC0, 0xD0]);
+    }
+
+    #[test]
+    fn nothing_read_without_a_touch() {
+        let mut touch = touch(false);
+        assert_eq!(touch.read().unwrap(), None);
+        assert!(commands(&touch).is_empty());
+    }
+
+    #[test]
+    fn mode_bits_go_into_every_command() {
+        // PENIRQ stays high with the converter on.
+        let mut touch = touch(false);
+        touch.set_power_down(PowerDown::AdcOn);
+        touch.set_resolution(Resolution::Bits8);
+        touch.i2c.respond(&[0x40]).respond(&[0xFF]).respond(&[0x12]);
+        assert_eq!(touch.read().unwrap(), Some((0xFF, 0x12)));
+        assert_eq!(commands(&touch), [0xE6, 0xC6, 0xD6]);
+    }
+
+    #[test]
+    fn adc_on_tells_contact_from_z1() {
+        let mut touch = touch(false);
+        touch.set_power_down(PowerDown::AdcOn);
+        touch.i2c.respond(&[0, 0]);
+        assert_eq!(touch.read().unwrap(), None);
+        assert_eq!(commands(&touch), [0xE4]);
+
+        respond_sample(&mut touch, [0x123, 0x456, 0, 0xABC]);
+        assert_eq!(touch.read_sample().unwrap(), None);
+        respond_sample(&mut touch, [0x123, 0x456, 0x789, 0xABC]);
+        let sample = touch.read_sample().unwrap().unwrap();
+        assert_eq!((sample.x, sample.y, sample.z1), (0x123, 0x456, 0x789));
+    }
+
+    #[test]
+    fn address_pins() {
+        let mut touch = touch(true).with_address(0x4B);
+        touch.i2c.respond(&[0, 0]);
+        touch.read_x().unwrap();
+        assert_eq!(touch.i2c.writes, [(0x4B, vec![0xC0])]);
+    }
+
+    #[test]
+    fn bus_errors_are_returned() {
+        let mut touch = touch(true);
+        touch.i2c.fail = Some(ErrorKind::Other);
+        assert!(matches!(touch.read(), Err(Error::I2C(ErrorKind::Other))));
+        assert!(matches!(touch.init(), Err(Error::I2C(ErrorKind::Other))));
+    }
+
+    /// Queues the replies to the X, Y, Z1 and Z2 conversions.
+    fn respond_sample(touch: &mut Touch, sample: [u16; 4]) {
+        for v in sample {
+            touch.i2c.respond(&[(v >> 4) as u8, (v << 4) as u8]);
+        }
+    }
+
+    #[test]
+    fn reads_all_four_channels() {
+        let mut touch = touch(true);
+        respond_sample(&mut touch, [0x123, 0x456, 0x789, 0xABC]);
+        let sample = touch.read_sample().unwrap().unwrap();
+        assert_eq!(
+            sample,
+            Sample {
+                x: 0x123,
+                y: 0x456,
+                z1: 0x789,
+                z2: 0xABC
+            }
+        );
+        assert_eq!(commands(&touch), [0xC0, 0xD0, 0xE0, 0xF0]);
+    }
+
+    #[test]
+    fn touch_resistance() {
+        let touch = touch(true).with_x_plate_ohms(400);
+        let sample = |z1, z2| Sample {
+            x: 2048,
+            y: 0,
+            z1,
+            z2,
+        };
+        // Half way along X, Z2 three times Z1: 400 * 0.5 * 2.
+        assert_eq!(touch.resistance(&sample(1024, 3072)), Some(400));
+        assert_eq!(touch.resistance(&sample(2000, 2000)), Some(0));
+        assert_eq!(touch.resistance(&sample(0, 3000)), None);
+        let mut coarse = touch;
+        coarse.set_resolution(Resolution::Bits8);
+        let sample = Sample {
+            x: 128,
+            ..sample(64, 192)
+        };
+        assert_eq!(coarse.resistance(&sample), Some(400));
+    }
+
+    /// Z2 for a touch resistance of `rt` ohms, with X at half scale, Z1 at
+    /// 1024 and a 1000 ohm X plate.
+    fn z2_for(rt: u32) -> u16 {
+        (1024 + rt * 1024 / 500) as u16
+    }
+
+    #[test]
+    fn pen_hysteresis() {
+        let mut touch = touch(true).with_x_plate_ohms(1000);
+        touch.set_pressure_threshold(Some(PressureThreshold {
+            press: 500,
+            release: 800,
+        }));
+        let mut points = Vec::new();
+        for (i, rt) in [900, 500, 700, 790, 850, 700, 400].into_iter().enumerate() {
+            respond_sample(&mut touch, [2048, i as u16, 1024, z2_for(rt)]);
+            points.push(touch.read().unwrap().map(|(_, y)| y));
+        }
+        // A graze doesn't start a contact, a firm press does and lasts
+        // until the pen is nearly lifted.
+        let expected = [None, Some(1), Some(2), Some(3), None, None, Some(6)];
+        assert_eq!(points, expected);
+        assert!(touch.is_pen_down());
+    }
+
+    #[test]
+    fn pen_lifts_with_penirq() {
+        let pin = MockInputPin::new(&[false, true]);
+        let mut touch = Tsc2007::new(pin, MockI2c::new()).with_x_plate_ohms(1000);
+        touch.set_pressure_threshold(Some(PressureThreshold {
+            press: 500,
+            release: 800,
+        }));
+        respond_sample(&mut touch, [2048, 7, 1024, z2_for(100)]);
+        assert_eq!(touch.read().unwrap(), Some((2048, 7)));
+        assert_eq!(touch.read().unwrap(), None);
+        assert!(!touch.is_pen_down());
+    }
+
+    /// PENIRQ pin that can't be read.
+    struct BrokenPin;
+
+    impl digital::ErrorType for BrokenPin {
+        type Error = digital::ErrorKind;
+    }
+
+    impl InputPin for BrokenPin {
+        fn is_high(&mut self) -> core::result::Result<bool, Self::Error> {
+            Err(digital::ErrorKind::Other)
+        }
+
+        fn is_low(&mut self) -> core::result::Result<bool, Self::Error> {
+            Err(digital::ErrorKind::Other)
+        }
+    }
+
+    #[test]
+    fn pin_errors_are_returned() {
+        let mut touch = Tsc2007::new(BrokenPin, MockI2c::new());
+        assert!(matches!(
+            touch.read(),
+            Err(Error::Pin(digital::ErrorKind::Other))
+        ));
+    }
+}